@@ -1,110 +1,874 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::env::consts::EXE_SUFFIX;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::str::FromStr;
 
 use util;
 use util::prompt_confirm;
 
-const WASM_BINDGEN_OUT_DIR: &str = "./target/wasm-bin";
-const INSTALL_PROMPT: &str =
-    "No installation of wasm-bindgen found. Do you want to install wasm-bindgen? (y/n): ";
+pub const WASM_BINDGEN_OUT_DIR: &str = "./target/wasm-bin";
+
+/// Environment variable setting the install policy when no flag is given: `always`, `never`
+/// or `prompt`
+pub const INSTALL_ENV: &str = "WASM_BIN_INSTALL";
+/// Environment variable pointing at a wasm-bindgen executable to use instead of a managed one
+pub const BINDGEN_ENV: &str = "WASM_BINDGEN";
+/// Environment variable overriding the directory tools are installed to
+pub const CACHE_DIR_ENV: &str = "WASM_BIN_CACHE_DIR";
 
 #[derive(Debug)]
 pub enum Error {
     InstallFailed,
     InstallCommandError(io::Error),
-    BindgenFailed,
+    /// wasm-bindgen is missing and installing it was declined or not allowed
+    NotInstalledError,
+    /// No directory to install tools to, since the home directory is unknown
+    NoCacheDirError,
+    ReadLockFileError(io::Error),
+    /// Cargo.lock contains several versions of the wasm-bindgen crate
+    AmbiguousVersionError(Vec<String>),
+    /// The module was built with a version of the wasm-bindgen crate the executable can't read,
+    /// along with the wasm-bindgen version to install when wasm-bindgen reports one
+    VersionMismatchError(Option<String>, Output),
+    /// The input isn't a wasm module wasm-bindgen can parse
+    InvalidWasmError(Output),
+    /// The executable doesn't support the flags wasm-bin passes
+    UnsupportedFlagsError(Output),
+    /// wasm-bindgen couldn't read the input module
+    MissingInputError(Output),
+    /// The input module doesn't exist, found before running wasm-bindgen
+    InputNotFoundError(PathBuf),
+    /// Any other failure
+    BindgenFailed(Output),
     BindgenCommandError(io::Error),
     PromptError(util::Error),
     CreateTargetDirectoryError(io::Error),
 }
 
-pub fn install_if_required(skip_prompt: Option<bool>) -> Result<(), Error> {
-    // check if wasm-bindgen CLI tool is installed, if not, ask the user to install it
-    match Command::new("wasm-bindgen")
-        .arg("-h")
-        .stdout(Stdio::piped())
-        .spawn()
-    {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-            io::ErrorKind::NotFound => {
-                let skip_prompt = skip_prompt.unwrap_or(false);
-                let do_install =
-                    skip_prompt || prompt_confirm(INSTALL_PROMPT).map_err(Error::PromptError)?;
-                if do_install {
-                    install()?;
-                    Ok(())
-                } else {
-                    Err(Error::BindgenCommandError(e))
+/// Captured output of a wasm-bindgen run
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Error {
+    /// Output of the failed wasm-bindgen run, if the error comes from one
+    pub fn output(&self) -> Option<&Output> {
+        match *self {
+            Error::VersionMismatchError(_, ref output)
+            | Error::InvalidWasmError(ref output)
+            | Error::UnsupportedFlagsError(ref output)
+            | Error::MissingInputError(ref output)
+            | Error::BindgenFailed(ref output) => Some(output),
+            _ => None,
+        }
+    }
+}
+
+/// Whether wasm-bindgen may be installed, or reinstalled, when needed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InstallPolicy {
+    /// Ask first, or don't install if stdin isn't a terminal
+    #[default]
+    Prompt,
+    Always,
+    Never,
+}
+
+impl FromStr for InstallPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InstallPolicy, String> {
+        match s {
+            "prompt" => Ok(InstallPolicy::Prompt),
+            "always" | "yes" => Ok(InstallPolicy::Always),
+            "never" | "no" => Ok(InstallPolicy::Never),
+            _ => Err(format!("unknown install policy `{}`", s)),
+        }
+    }
+}
+
+/// Where wasm-bindgen-cli is installed from
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum InstallSource {
+    #[default]
+    CratesIo,
+    /// A local checkout of the wasm-bindgen-cli crate
+    Path(PathBuf),
+    /// A directory of crates vendored with `cargo vendor`, installed from offline
+    Vendored(PathBuf),
+}
+
+/// A wasm-bindgen executable
+#[derive(Debug)]
+pub struct Bindgen {
+    pub path: PathBuf,
+    /// Version locked by the project, `None` for the latest version
+    pub version: Option<String>,
+    /// Whether the executable is installed in the cache of wasm-bin, which may reinstall it
+    pub managed: bool,
+    policy: InstallPolicy,
+    source: InstallSource,
+}
+
+fn confirm_install(policy: InstallPolicy, prompt: &str) -> Result<bool, Error> {
+    match policy {
+        InstallPolicy::Always => Ok(true),
+        InstallPolicy::Never => Ok(false),
+        InstallPolicy::Prompt if !io::stdin().is_terminal() => {
+            println!("wasm-bin: Not asking to install wasm-bindgen, stdin is not a terminal");
+            Ok(false)
+        }
+        InstallPolicy::Prompt => prompt_confirm(prompt).map_err(Error::PromptError),
+    }
+}
+
+/// Directory wasm-bin installs tools to, `~/.cache/wasm-bin` by default
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(CACHE_DIR_ENV) {
+        return Some(PathBuf::from(dir));
+    }
+    let cache = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache.join("wasm-bin"))
+}
+
+/// Install root of a version of wasm-bindgen in the cache, `latest` if no version is locked
+pub fn install_dir(version: Option<&str>) -> Option<PathBuf> {
+    Some(
+        cache_dir()?
+            .join("wasm-bindgen")
+            .join(version.unwrap_or("latest")),
+    )
+}
+
+// Install root of the executable a source provides. A checkout gets its own directory, since
+// its version may not be the locked one.
+fn install_root(version: Option<&str>, source: &InstallSource) -> Option<PathBuf> {
+    match *source {
+        InstallSource::Path(ref path) => {
+            let mut hasher = DefaultHasher::new();
+            path.canonicalize()
+                .unwrap_or_else(|_| path.clone())
+                .hash(&mut hasher);
+            Some(
+                cache_dir()?
+                    .join("wasm-bindgen")
+                    .join(format!("path-{:016x}", hasher.finish())),
+            )
+        }
+        InstallSource::CratesIo | InstallSource::Vendored(_) => install_dir(version),
+    }
+}
+
+/// Version of the wasm-bindgen crate in a Cargo.lock file. Several versions are an error, since
+/// there is no telling which one the module is built with.
+pub fn locked_version(lock_file: &Path) -> Result<Option<String>, Error> {
+    let lock = match fs::read_to_string(lock_file) {
+        Ok(lock) => lock,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::ReadLockFileError(e)),
+    };
+    let mut versions = Vec::new();
+    let mut in_bindgen = false;
+    for line in lock.lines().map(str::trim) {
+        if line == "[[package]]" {
+            in_bindgen = false;
+        } else if line == "name = \"wasm-bindgen\"" {
+            in_bindgen = true;
+        } else if in_bindgen && line.starts_with("version = ") {
+            versions.push(line["version = ".len()..].trim_matches('"').to_string());
+            in_bindgen = false;
+        }
+    }
+    if versions.len() > 1 {
+        println!(
+            "wasm-bin: {} locks several versions of wasm-bindgen ({}). Set {} to the wasm-bindgen \
+             executable matching the version your targets use",
+            lock_file.display(),
+            versions.join(", "),
+            BINDGEN_ENV
+        );
+        return Err(Error::AmbiguousVersionError(versions));
+    }
+    Ok(versions.pop())
+}
+
+// Version of a wasm-bindgen executable, which prints e.g. `wasm-bindgen 0.2.87` for `--version`
+fn installed_version(program: &Path) -> Option<String> {
+    let output = Command::new(program)
+        .arg("--version")
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .nth(1)
+        .map(String::from)
+}
+
+/// Finds the wasm-bindgen executable for a project whose dependencies are locked in a
+/// Cargo.lock file, installing the locked version to the cache if needed. The executable
+/// named by `WASM_BINDGEN` is used as is.
+pub fn find(
+    lock_file: &Path,
+    policy: InstallPolicy,
+    source: &InstallSource,
+) -> Result<Bindgen, Error> {
+    if let Some(path) = env::var_os(BINDGEN_ENV) {
+        return Ok(Bindgen {
+            path: PathBuf::from(path),
+            version: None,
+            managed: false,
+            policy,
+            source: source.clone(),
+        });
+    }
+
+    let version = locked_version(lock_file)?;
+    if !matches!(*source, InstallSource::Path(_)) {
+        // An executable on the PATH will do if it has the locked version, or any version if
+        // none is locked
+        let path_version = installed_version(Path::new("wasm-bindgen"));
+        if path_version.is_some() && (version.is_none() || path_version == version) {
+            return Ok(Bindgen {
+                path: PathBuf::from("wasm-bindgen"),
+                version,
+                managed: false,
+                policy,
+                source: source.clone(),
+            });
+        }
+    }
+
+    let install_dir = install_root(version.as_deref(), source).ok_or(Error::NoCacheDirError)?;
+    let bindgen = Bindgen {
+        path: install_dir
+            .join("bin")
+            .join(format!("wasm-bindgen{}", EXE_SUFFIX)),
+        version,
+        managed: true,
+        policy,
+        source: source.clone(),
+    };
+    if !bindgen.path.is_file() {
+        let prompt = format!(
+            "wasm-bindgen {} is not installed. Do you want to install it to {}? (y/n): ",
+            bindgen.version_name(),
+            install_dir.display()
+        );
+        if !confirm_install(policy, &prompt)? {
+            println!(
+                "wasm-bin: wasm-bindgen {} is not installed. Pass --yes or set {}=always to let \
+                 wasm-bin install it, or set {} to the path of a wasm-bindgen executable",
+                bindgen.version_name(),
+                INSTALL_ENV,
+                BINDGEN_ENV
+            );
+            return Err(Error::NotInstalledError);
+        }
+        bindgen.install(false)?;
+    }
+    Ok(bindgen)
+}
+
+// The value following the first of some labels in a text, e.g. `rust wasm file: 0.2.87`
+fn value_after(text: &str, labels: &[&str]) -> Option<String> {
+    labels.iter().find_map(|label| {
+        let start = text.find(label)? + label.len();
+        text[start..]
+            .split_whitespace()
+            .next()
+            .map(|value| value.to_string())
+    })
+}
+
+/// Recognizes why wasm-bindgen failed from its output. Parse errors can mention reading the
+/// input too, so they are checked before read errors
+pub fn diagnose(output: Output) -> Error {
+    let text = format!("{}\n{}", output.stdout, output.stderr);
+    let lower = text.to_lowercase();
+    let matches = |patterns: &[&str]| patterns.iter().any(|pattern| lower.contains(pattern));
+
+    if matches(&["schema version", "different bindgen format"]) {
+        // The schema version of the module isn't always a released wasm-bindgen version, so
+        // the install command wasm-bindgen suggests is preferred
+        let version = value_after(
+            &lower,
+            &[
+                "cargo install -f wasm-bindgen-cli --version",
+                "rust wasm file schema version:",
+                "rust wasm file:",
+            ],
+        );
+        Error::VersionMismatchError(version, output)
+    } else if matches(&[
+        "unexpected argument",
+        "cannot be used with",
+        "wasn't expected",
+        "unknown flag",
+        "unrecognized option",
+    ]) {
+        Error::UnsupportedFlagsError(output)
+    } else if matches(&[
+        "failed to parse input",
+        "failed to deserialize wasm",
+        "magic header not detected",
+        "unknown binary version",
+    ]) {
+        Error::InvalidWasmError(output)
+    } else if matches(&[
+        "failed reading",
+        "failed to read",
+        "no such file",
+        "cannot find the file",
+    ]) {
+        Error::MissingInputError(output)
+    } else {
+        Error::BindgenFailed(output)
+    }
+}
+
+impl Bindgen {
+    fn version_name(&self) -> String {
+        match self.source {
+            InstallSource::Path(ref path) => format!("from {}", path.display()),
+            InstallSource::CratesIo | InstallSource::Vendored(_) => {
+                self.version.as_deref().unwrap_or("latest").to_string()
+            }
+        }
+    }
+
+    fn install(&self, force: bool) -> Result<(), Error> {
+        let root = self.path.parent().and_then(Path::parent).unwrap();
+        println!(
+            "wasm-bin: Install wasm-bindgen {} to {}",
+            self.version_name(),
+            root.display()
+        );
+        let mut cmd = Command::new("cargo");
+        cmd.arg("install").arg("--root").arg(root);
+        match self.source {
+            InstallSource::Path(ref path) => {
+                cmd.arg("--path").arg(path);
+            }
+            InstallSource::CratesIo | InstallSource::Vendored(_) => {
+                cmd.arg("wasm-bindgen-cli");
+                if let Some(ref version) = self.version {
+                    cmd.arg("--version").arg(format!("={}", version));
                 }
             }
-            _ => Err(Error::BindgenCommandError(e)),
-        },
+        }
+        if let InstallSource::Vendored(ref dir) = self.source {
+            let dir = dir.canonicalize().map_err(Error::InstallCommandError)?;
+            cmd.arg("--offline")
+                .arg("--config")
+                .arg("source.crates-io.replace-with=\"vendored-sources\"")
+                .arg("--config")
+                .arg(format!(
+                    "source.vendored-sources.directory={:?}",
+                    dir.to_string_lossy()
+                ));
+        }
+        if force {
+            cmd.arg("--force");
+        }
+
+        match cmd.status() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(_) => Err(Error::InstallFailed),
+            Err(e) => Err(Error::InstallCommandError(e)),
+        }
     }
-}
 
-fn install() -> Result<(), Error> {
-    println!("wasm-bin: Install wasm-bindgen");
-    let mut install = Command::new("cargo")
-        .arg("install")
-        .arg("wasm-bindgen-cli")
-        .arg("-f")
-        .spawn()
-        .map_err(Error::InstallCommandError)?;
+    // A managed executable of another version, sharing the install policy and source
+    fn with_version(&self, version: Option<String>) -> Result<Bindgen, Error> {
+        let install_dir =
+            install_root(version.as_deref(), &self.source).ok_or(Error::NoCacheDirError)?;
+        Ok(Bindgen {
+            path: install_dir
+                .join("bin")
+                .join(format!("wasm-bindgen{}", EXE_SUFFIX)),
+            version,
+            managed: true,
+            policy: self.policy,
+            source: self.source.clone(),
+        })
+    }
+
+    // Runs wasm-bindgen, capturing its output and diagnosing failures
+    fn run(&self, input_file: &Path, out_dir: &Path) -> Result<(), Error> {
+        let output = Command::new(&self.path)
+            .arg(input_file)
+            // Generates a script defining a global `wasm_bindgen`, which the loader calls
+            .arg("--target")
+            .arg("no-modules")
+            .arg("--out-dir")
+            .arg(out_dir)
+            .output()
+            .map_err(Error::BindgenCommandError)?;
+        let output_text = Output {
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        };
+        if output.status.success() {
+            // Forward warnings
+            print!("{}", output_text.stdout);
+            eprint!("{}", output_text.stderr);
+            Ok(())
+        } else {
+            Err(diagnose(output_text))
+        }
+    }
+
+    /// Generates the javascript bindings of a target, returning the paths of the generated
+    /// javascript and wasm module
+    pub fn generate(
+        &self,
+        profile_dir: &str,
+        target_name: &str,
+        input_file: &Path,
+    ) -> Result<(PathBuf, PathBuf), Error> {
+        if !input_file.is_file() {
+            return Err(Error::InputNotFoundError(input_file.to_path_buf()));
+        }
+
+        // Create target directory if it doesn't exist
+        let out_dir = out_dir(profile_dir, target_name);
+        match fs::read_dir(&out_dir) {
+            Ok(_) => {}
+            Err(e) => match e.kind() {
+                io::ErrorKind::NotFound => {
+                    fs::create_dir_all(&out_dir).map_err(Error::CreateTargetDirectoryError)?;
+                }
+                _ => return Err(Error::BindgenCommandError(e)),
+            },
+        }
+
+        match self.run(input_file, &out_dir) {
+            Ok(()) => {}
+            // Only a version mismatch is fixed by installing another version, and only for
+            // executables wasm-bin installed itself from crates. The version locked in
+            // Cargo.lock is the one the module was built with, when there is one
+            Err(Error::VersionMismatchError(version, output))
+                if self.managed && !matches!(self.source, InstallSource::Path(_)) =>
+            {
+                let bindgen = self.with_version(self.version.clone().or(version))?;
+                let prompt = format!(
+                    "wasm-bindgen {} can't read the module, which was built with wasm-bindgen {}. \
+                     Do you want to install wasm-bindgen {}? (y/n): ",
+                    self.version_name(),
+                    bindgen.version_name(),
+                    bindgen.version_name()
+                );
+                if !confirm_install(self.policy, &prompt)? {
+                    report(&output);
+                    return Err(Error::VersionMismatchError(bindgen.version, output));
+                }
+                // Reinstall over a broken executable of the same version
+                bindgen.install(bindgen.path.is_file())?;
+                bindgen.run(input_file, &out_dir).inspect_err(|e| {
+                    if let Some(output) = e.output() {
+                        report(output);
+                    }
+                })?;
+            }
+            Err(e) => {
+                if let Some(output) = e.output() {
+                    report(output);
+                }
+                return Err(e);
+            }
+        }
 
-    match install.wait() {
-        Ok(status) => match status.success() {
-            true => Ok(()),
-            false => Err(Error::InstallFailed),
-        },
-        Err(e) => Err(Error::InstallCommandError(e)),
+        let mut js_out = out_dir.clone();
+        js_out.push(format!("{}.js", target_name));
+        let mut wasm_out = out_dir;
+        wasm_out.push(format!("{}_bg.wasm", target_name));
+        Ok((js_out, wasm_out))
     }
 }
 
-pub fn generate(target_name: &str, input_file: &Path) -> Result<(PathBuf, PathBuf), Error> {
-    // Create target directory if it doesn't exist
+// Prints the output of a failed run
+fn report(output: &Output) {
+    println!("wasm-bin: wasm-bindgen failed:");
+    print!("{}", output.stdout);
+    eprint!("{}", output.stderr);
+}
+
+/// Directory wasm-bindgen writes the outputs of a target built with a profile to
+pub fn out_dir(profile_dir: &str, target_name: &str) -> PathBuf {
     let mut out_dir = PathBuf::from(WASM_BINDGEN_OUT_DIR);
+    out_dir.push(profile_dir);
     out_dir.push(target_name);
-    match fs::read_dir(&out_dir) {
-        Ok(_) => {}
-        Err(e) => match e.kind() {
-            io::ErrorKind::NotFound => {
-                fs::create_dir_all(&out_dir).map_err(Error::CreateTargetDirectoryError)?;
+    out_dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+    use std::sync::{Mutex, MutexGuard};
+
+    const LOCK_FILE: &str = r#"# This file is automatically @generated by Cargo.
+version = 3
+
+[[package]]
+name = "wasm-bindgen"
+version = "0.2.87"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = [
+ "wasm-bindgen-macro",
+]
+
+[[package]]
+name = "wasm-bindgen-macro"
+version = "0.2.88"
+"#;
+
+    // Held by tests changing the environment, which is shared by the test threads. The variables
+    // they change are restored when it is dropped.
+    struct Env {
+        _lock: MutexGuard<'static, ()>,
+        vars: Vec<(&'static str, Option<OsString>)>,
+    }
+
+    impl Env {
+        fn lock() -> Env {
+            static LOCK: Mutex<()> = Mutex::new(());
+            let lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let vars = ["PATH", BINDGEN_ENV, CACHE_DIR_ENV]
+                .iter()
+                .map(|name| (*name, env::var_os(name)))
+                .collect();
+            Env { _lock: lock, vars }
+        }
+    }
+
+    impl Drop for Env {
+        fn drop(&mut self) {
+            for (name, value) in &self.vars {
+                match value {
+                    Some(value) => env::set_var(name, value),
+                    None => env::remove_var(name),
+                }
             }
-            _ => return Err(Error::BindgenCommandError(e)),
-        },
+        }
     }
 
-    for _ in 0..2 {
-        let retry = match Command::new("wasm-bindgen")
-            .arg(&input_file)
-            .arg("--browser")
-            .arg("--no-modules")
-            .arg("--out-dir")
-            .arg(&out_dir)
-            .spawn()
-            .map_err(Error::BindgenCommandError)?
-            .wait()
-        {
-            Ok(status) => !status.success(),
-            Err(e) => return Err(Error::BindgenCommandError(e)),
-        };
+    #[test]
+    fn find_cached_versions() {
+        let _env = Env::lock();
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/bindgen-cache");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let lock_file = dir.join("Cargo.lock");
+        fs::write(&lock_file, LOCK_FILE).unwrap();
+        assert_eq!(
+            locked_version(&lock_file).unwrap(),
+            Some("0.2.87".to_string())
+        );
+        assert_eq!(locked_version(&dir.join("missing.lock")).unwrap(), None);
 
-        if retry {
-            println!(
-                "wasm-bin: wasm-bindgen failed, try installing latest version of wasm-bindgen"
-            );
-            install()?;
-        } else {
-            break;
+        env::remove_var(BINDGEN_ENV);
+        // No wasm-bindgen on the PATH
+        fs::create_dir_all(dir.join("path")).unwrap();
+        env::set_var("PATH", dir.join("path"));
+        env::set_var(CACHE_DIR_ENV, dir.join("cache"));
+        let installed = dir.join("cache/wasm-bindgen/0.2.87");
+        assert_eq!(install_dir(Some("0.2.87")), Some(installed.clone()));
+
+        // Not installed, and installing isn't allowed
+        match find(&lock_file, InstallPolicy::Never, &InstallSource::CratesIo) {
+            Err(Error::NotInstalledError) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let executable = installed
+            .join("bin")
+            .join(format!("wasm-bindgen{}", env::consts::EXE_SUFFIX));
+        fs::create_dir_all(executable.parent().unwrap()).unwrap();
+        fs::write(&executable, "").unwrap();
+        let found = find(&lock_file, InstallPolicy::Never, &InstallSource::CratesIo).unwrap();
+        assert_eq!(found.path, executable);
+        assert_eq!(found.version, Some("0.2.87".to_string()));
+        assert!(found.managed);
+
+        env::set_var(BINDGEN_ENV, "/opt/wasm-bindgen");
+        let found = find(&lock_file, InstallPolicy::Never, &InstallSource::CratesIo).unwrap();
+        assert_eq!(found.path, PathBuf::from("/opt/wasm-bindgen"));
+        assert!(!found.managed);
+
+        env::remove_var(BINDGEN_ENV);
+
+        // A checkout is installed to its own directory rather than under the locked version
+        let checkout = dir.join("wasm-bindgen-cli");
+        fs::create_dir_all(&checkout).unwrap();
+        match find(
+            &lock_file,
+            InstallPolicy::Never,
+            &InstallSource::Path(checkout),
+        ) {
+            Err(Error::NotInstalledError) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_matching_version_on_path() {
+        let _env = Env::lock();
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/bindgen-path");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        let lock_file = dir.join("Cargo.lock");
+        fs::write(&lock_file, LOCK_FILE).unwrap();
+        let executable = dir.join("bin/wasm-bindgen");
+        fs::write(&executable, "#!/bin/sh\necho wasm-bindgen 0.2.87\n").unwrap();
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+        env::remove_var(BINDGEN_ENV);
+        env::set_var(CACHE_DIR_ENV, dir.join("cache"));
+        env::set_var("PATH", dir.join("bin"));
+
+        // The locked version is on the PATH, so nothing has to be installed
+        let found = find(&lock_file, InstallPolicy::Never, &InstallSource::CratesIo).unwrap();
+        assert_eq!(found.path, PathBuf::from("wasm-bindgen"));
+        assert_eq!(found.version, Some("0.2.87".to_string()));
+        assert!(!found.managed);
+
+        // Another version on the PATH isn't used
+        fs::write(&executable, "#!/bin/sh\necho wasm-bindgen 0.2.92\n").unwrap();
+        match find(&lock_file, InstallPolicy::Never, &InstallSource::CratesIo) {
+            Err(Error::NotInstalledError) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_several_locked_versions() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/bindgen-lock");
+        fs::create_dir_all(&dir).unwrap();
+        let lock_file = dir.join("Cargo.lock");
+        let lock = format!(
+            "{}\n[[package]]\nname = \"wasm-bindgen\"\nversion = \"0.2.92\"\n",
+            LOCK_FILE
+        );
+        fs::write(&lock_file, lock).unwrap();
+        match locked_version(&lock_file) {
+            Err(Error::AmbiguousVersionError(versions)) => {
+                assert_eq!(versions, vec!["0.2.87", "0.2.92"]);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    fn stderr(text: &str) -> Output {
+        Output {
+            stdout: String::new(),
+            stderr: text.to_string(),
         }
     }
 
-    let mut js_out = out_dir.clone();
-    js_out.push(format!("{}.js", target_name));
-    let mut wasm_out = out_dir;
-    wasm_out.push(format!("{}_bg.wasm", target_name));
-    Ok((js_out, wasm_out))
+    // Output of wasm-bindgen 0.2.129 for a module built with the 0.2.100 crate
+    const SCHEMA_MISMATCH: &str = "\
+error: failed to read wasm-bindgen data from 'target/wasm32-unknown-unknown/debug/app.wasm'
+
+Caused by:
+    it looks like the Rust project used to create this Wasm file was linked against
+    version of wasm-bindgen that uses a different bindgen format than this binary:
+    
+      rust Wasm file schema version: 0.2.100
+         this binary schema version: 0.2.129
+                   this binary path: /root/.cache/wasm-bin/wasm-bindgen/0.2.129/bin/wasm-bindgen
+    
+    Currently the bindgen format is unstable enough that these two schema versions
+    must exactly match. You can accomplish this by either updating this binary or
+    the wasm-bindgen dependency in the Rust project.
+    
+    You should be able to update the wasm-bindgen dependency with:
+    
+        cargo update -p wasm-bindgen --precise 0.2.129
+    
+    don't forget to recompile your Wasm file! Alternatively, you can update the
+    binary with:
+    
+        cargo install -f wasm-bindgen-cli --version 0.2.100
+    
+    if this warning fails to go away though and you're not sure what to do feel free
+    to open an issue at https://github.com/wasm-bindgen/wasm-bindgen/issues!
+";
+
+    // Output of wasm-bindgen 0.2.87 for a module built with the 0.2.92 crate, whose schema version
+    // is 0.2.88, which is no wasm-bindgen-cli release
+    const SCHEMA_VERSION_MISMATCH: &str = "\
+error: failed to read wasm-bindgen data from 'target/wasm32-unknown-unknown/debug/app.wasm'
+
+Caused by:
+    it looks like the Rust project used to create this wasm file was linked against
+    version of wasm-bindgen that uses a different bindgen format than this binary:
+
+      rust wasm file schema version: 0.2.88
+         this binary schema version: 0.2.87
+
+    Currently the bindgen format is unstable enough that these two schema versions
+    must exactly match. You can accomplish this by either updating the wasm-bindgen
+    dependency or this binary.
+
+    You should be able to update the wasm-bindgen dependency with:
+
+        cargo update -p wasm-bindgen --precise 0.2.87
+
+    don't forget to recompile your wasm file! Alternatively, you can update the
+    binary with:
+
+        cargo install -f wasm-bindgen-cli --version 0.2.92
+";
+
+    // Output of wasm-bindgen 0.2.129 given the flags older wasm-bin versions passed
+    const UNSUPPORTED_FLAGS: &str = "\
+error: error: the argument '--browser' cannot be used with '--no-modules'
+
+Usage: wasm-bindgen --out-dir <DIR> --browser <INPUT>
+
+For more information, try '--help'.
+";
+
+    const UNKNOWN_FLAG: &str = "\
+error: error: unexpected argument '--frobnicate' found
+
+  tip: to pass '--frobnicate' as a value, use '-- --frobnicate'
+
+Usage: wasm-bindgen --out-dir <DIR> <INPUT>
+
+For more information, try '--help'.
+";
+
+    const MISSING_INPUT: &str = "\
+error: failed reading 'missing.wasm'
+
+Caused by:
+    No such file or directory (os error 2)
+";
+
+    const INVALID_WASM: &str = "\
+error: failed getting Wasm module for 'bad.wasm'
+
+Caused by:
+    0: failed to parse input as wasm
+    1: magic header not detected: bad magic number - expected=[
+           0x0,
+           0x61,
+           0x73,
+           0x6d,
+       ] actual=[
+           0x67,
+           0x61,
+           0x72,
+           0x62,
+       ] (at offset 0x0)
+";
+
+    #[test]
+    fn diagnose_failures() {
+        match diagnose(stderr(SCHEMA_MISMATCH)) {
+            Error::VersionMismatchError(Some(ref version), ref output) => {
+                assert_eq!(version, "0.2.100");
+                assert_eq!(output.stderr, SCHEMA_MISMATCH);
+            }
+            other => panic!("unexpected diagnosis {:?}", other),
+        }
+        // The version wasm-bindgen suggests installing is preferred over the schema version
+        match diagnose(stderr(SCHEMA_VERSION_MISMATCH)) {
+            Error::VersionMismatchError(Some(ref version), _) => assert_eq!(version, "0.2.92"),
+            other => panic!("unexpected diagnosis {:?}", other),
+        }
+        let without_hint =
+            &SCHEMA_VERSION_MISMATCH[..SCHEMA_VERSION_MISMATCH.find("    You").unwrap()];
+        match diagnose(stderr(without_hint)) {
+            Error::VersionMismatchError(Some(ref version), _) => assert_eq!(version, "0.2.88"),
+            other => panic!("unexpected diagnosis {:?}", other),
+        }
+        for sample in &[UNSUPPORTED_FLAGS, UNKNOWN_FLAG] {
+            match diagnose(stderr(sample)) {
+                Error::UnsupportedFlagsError(_) => {}
+                other => panic!("unexpected diagnosis {:?}", other),
+            }
+        }
+        match diagnose(stderr(MISSING_INPUT)) {
+            Error::MissingInputError(_) => {}
+            other => panic!("unexpected diagnosis {:?}", other),
+        }
+        match diagnose(stderr(INVALID_WASM)) {
+            Error::InvalidWasmError(_) => {}
+            other => panic!("unexpected diagnosis {:?}", other),
+        }
+        let error = diagnose(stderr("thread 'main' panicked\n"));
+        match error {
+            Error::BindgenFailed(_) => {}
+            ref other => panic!("unexpected diagnosis {:?}", other),
+        }
+        assert_eq!(error.output().unwrap().stderr, "thread 'main' panicked\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mismatch_without_reinstalling() {
+        let _env = Env::lock();
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/tests/bindgen-failures");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("app.wasm");
+        fs::write(&input, b"\0asm\x01\0\0\0").unwrap();
+        let executable = dir.join("wasm-bindgen");
+        fs::write(
+            &executable,
+            format!(
+                "#!/bin/sh\ncat >&2 <<'EOF'\n{}EOF\nexit 1\n",
+                SCHEMA_MISMATCH
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // Executables set with WASM_BINDGEN aren't managed, so they are never reinstalled
+        env::set_var(BINDGEN_ENV, &executable);
+        let bindgen = find(
+            &dir.join("Cargo.lock"),
+            InstallPolicy::Always,
+            &InstallSource::CratesIo,
+        )
+        .unwrap();
+        assert!(!bindgen.managed);
+        match bindgen.generate("debug", "app", &input) {
+            Err(Error::VersionMismatchError(Some(ref version), ref output)) => {
+                assert_eq!(version, "0.2.100");
+                assert!(output
+                    .stderr
+                    .contains("this binary schema version: 0.2.129"));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // A missing input is reported without running wasm-bindgen
+        match bindgen.generate("debug", "app", &dir.join("missing.wasm")) {
+            Err(Error::InputNotFoundError(ref path)) => assert_eq!(path, &dir.join("missing.wasm")),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
 }
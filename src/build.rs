@@ -1,20 +1,41 @@
+use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use bindgen;
+/// wasm-bindgen errors, carried by `Error::BindgenError`, and the output of failed runs
+pub use bindgen::{Error as BindgenError, Output as BindgenOutput};
+pub use bindgen::{InstallPolicy, InstallSource, BINDGEN_ENV, INSTALL_ENV};
 use cargo;
 use cargo::WasmArtifact;
+/// Cargo errors, carried by `Error::CargoBuildError`, and the compiler diagnostics of failed builds
+pub use cargo::{Diagnostic, Error as CargoError};
+use compress;
+use html;
+/// The function the page calls once the module is loaded, and its arguments
+pub use html::{Entry, EntryArgs};
+use imports;
+use strip;
+use util;
+use validate;
 
-#[cfg(test)]
-const SKIP_PROMPT: bool = true;
-#[cfg(not(test))]
-const SKIP_PROMPT: bool = false;
+/// Directory of the workspace whose files are copied next to the outputs of every target
+pub const STATIC_DIR: &str = "static";
 
 #[derive(Debug)]
 pub enum Error {
     CargoBuildError(cargo::Error),
     BindgenError(bindgen::Error),
     ExportMainError(io::Error),
+    RenderHtmlError(html::Error),
+    WriteHtmlError(io::Error),
+    CopyStaticFilesError(io::Error),
+    PrecompressError(io::Error),
+    ReadBindingsError(io::Error),
+    WriteImportStubsError(io::Error),
+    RemoveImportStubsError(io::Error),
+    StripError(strip::Error),
+    ValidateError(validate::Error),
 }
 
 #[derive(Default)]
@@ -29,6 +50,8 @@ pub struct Options {
     pub example: Option<String>,
     pub all_targets: bool,
     pub release: bool,
+    /// Cargo profile to build with, instead of `dev` or `release`
+    pub profile: Option<String>,
     pub features: Option<String>,
     pub all_features: bool,
     pub no_default_features: bool,
@@ -38,6 +61,26 @@ pub struct Options {
     pub frozen: bool,
     pub locked: bool,
     pub cargo_flags: Option<String>,
+    /// Whether wasm-bindgen may be installed when it is missing or fails
+    pub install: InstallPolicy,
+    /// Where wasm-bindgen is installed from
+    pub bindgen_source: InstallSource,
+    pub entry: Entry,
+    /// Also write brotli and gzip compressed siblings of the output files
+    pub precompress: bool,
+    /// Write placeholders for the functions targets import from the page, loaded by the
+    /// default page
+    pub import_stubs: bool,
+    /// Remove custom sections, such as names and DWARF debug info, from the wasm modules
+    pub strip_debug: bool,
+    /// Like `strip_debug`, but keep the unstripped module as `<target>.debug.wasm`
+    pub split_debug: bool,
+}
+
+/// Directory of the outputs and caches of wasm-bin, `target/wasm-bin` in the root of the
+/// workspace of a manifest, or of the current directory
+pub fn output_root(manifest_path: Option<&str>) -> Result<PathBuf, CargoError> {
+    Ok(cargo::workspace_root(manifest_path)?.join("target/wasm-bin"))
 }
 
 #[derive(Debug)]
@@ -50,7 +93,27 @@ pub enum PackageType {
 pub struct TargetPackage {
     pub ty: PackageType,
     pub name: String,
+    /// Generated javascript bindings
     pub path: PathBuf,
+    /// Generated wasm module
+    pub wasm_path: PathBuf,
+}
+
+/// Name of the directory the outputs of the selected profile go to, `debug` for the `dev`
+/// profile like cargo does
+pub fn profile_dir(options: &Options) -> String {
+    match options.profile.as_deref() {
+        Some("dev") | Some("test") => "debug".to_string(),
+        Some("bench") => "release".to_string(),
+        Some(profile) => profile.to_string(),
+        None if options.release => "release".to_string(),
+        None => "debug".to_string(),
+    }
+}
+
+/// Path of the wasm module generated for a target with a profile
+pub fn wasm_path(profile_dir: &str, target_name: &str) -> PathBuf {
+    bindgen::out_dir(profile_dir, target_name).join(format!("{}_bg.wasm", target_name))
 }
 
 pub fn build(options: &Options) -> Result<Vec<TargetPackage>, Error> {
@@ -66,6 +129,7 @@ pub fn build(options: &Options) -> Result<Vec<TargetPackage>, Error> {
         example: options.example.clone(),
         all_targets: options.all_targets.clone(),
         release: options.release.clone(),
+        profile: options.profile.clone(),
         features: options.features.clone(),
         all_features: options.all_features.clone(),
         no_default_features: options.no_default_features.clone(),
@@ -78,22 +142,221 @@ pub fn build(options: &Options) -> Result<Vec<TargetPackage>, Error> {
     };
     let artifacts = cargo::build(&cargo_options).map_err(Error::CargoBuildError)?;
 
-    bindgen::install_if_required(Some(SKIP_PROMPT)).map_err(Error::BindgenError)?;
+    let workspace_root =
+        cargo::workspace_root(options.manifest_path.as_deref()).map_err(Error::CargoBuildError)?;
+    let bindgen = bindgen::find(
+        &workspace_root.join("Cargo.lock"),
+        options.install,
+        &options.bindgen_source,
+    )
+    .map_err(Error::BindgenError)?;
+    let profile_dir = profile_dir(options);
     let mut targets = Vec::new();
     for a in artifacts {
-        let (package_type, target, path) = match a {
-            WasmArtifact::Binary(target, path) => (PackageType::Binary, target, path),
-            WasmArtifact::Library(target, path) => (PackageType::Library, target, path),
+        let (package_type, target, path, profile) = match a {
+            WasmArtifact::Binary(target, path, profile) => {
+                (PackageType::Binary, target, path, profile)
+            }
+            WasmArtifact::Library(target, path, profile) => {
+                (PackageType::Library, target, path, profile)
+            }
         };
 
-        println!("wasm-bin: Generate js bindings for target '{}'", target);
-        let (mut js_out, _) = bindgen::generate(&target, &path).map_err(Error::BindgenError)?;
+        println!(
+            "wasm-bin: Generate js bindings for target '{}' ({}, opt-level {}, debuginfo {})",
+            target,
+            profile_dir,
+            profile.opt_level,
+            profile.debuginfo.unwrap_or(0)
+        );
+        let (js_out, wasm_out) = bindgen
+            .generate(&profile_dir, &target, &path)
+            .map_err(Error::BindgenError)?;
+        if options.split_debug {
+            let debug_path =
+                strip::split_debug_info(&wasm_out, &target).map_err(Error::StripError)?;
+            println!(
+                "wasm-bin: Strip debug info of target '{}', kept in {}",
+                target,
+                debug_path.display()
+            );
+        } else if options.strip_debug {
+            println!("wasm-bin: Strip debug info of target '{}'", target);
+            strip::strip_file(&wasm_out).map_err(Error::StripError)?;
+        }
+
+        let interface = check_interface(&target, &package_type, &wasm_out, options)?;
+        let stubs_out = check_host_imports(&target, &js_out, &interface, options)?;
+
+        let mut html_context = html::Context::new(&target);
+        html_context.entry = options.entry.clone();
+        html_context.import_stubs = stubs_out.is_some();
+        let index = html::render_index(&html_context).map_err(Error::RenderHtmlError)?;
+        let index_out = js_out.with_file_name("index.html");
+        fs::write(&index_out, index).map_err(Error::WriteHtmlError)?;
+
+        copy_static_files(
+            &target,
+            &workspace_root.join(STATIC_DIR),
+            js_out.parent().unwrap(),
+        )
+        .map_err(Error::CopyStaticFilesError)?;
+
+        if options.precompress {
+            println!("wasm-bin: Compress output files for target '{}'", target);
+            let mut files = vec![&js_out, &wasm_out, &index_out];
+            files.extend(stubs_out.as_ref());
+            for file in files {
+                compress::precompress(file).map_err(Error::PrecompressError)?;
+            }
+        }
+
         targets.push(TargetPackage {
             ty: package_type,
             name: target,
             path: js_out,
+            wasm_path: wasm_out,
         });
     }
 
     Ok(targets)
 }
+
+// Whether a file in the output directory of a target is generated by the build: the index page
+// and the outputs of wasm-bindgen, which are named after the target
+fn is_generated(target: &str, file_name: &str) -> bool {
+    file_name == "index.html"
+        || file_name.starts_with(&format!("{}.", target))
+        || file_name.starts_with(&format!("{}_", target))
+}
+
+// Copies the files in the static directory to the output directory of a target, skipping files
+// that would replace generated outputs
+fn copy_static_files(target: &str, static_dir: &Path, out_dir: &Path) -> io::Result<()> {
+    if !static_dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(static_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let to = out_dir.join(&file_name);
+        if entry.file_type()?.is_dir() {
+            util::copy_dir(&entry.path(), &to)?;
+        } else if is_generated(target, &file_name.to_string_lossy()) {
+            println!(
+                "wasm-bin: Warning: not copying {}, it would replace an output of target '{}'",
+                entry.path().display(),
+                target
+            );
+        } else {
+            fs::copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+// Validates the generated wasm module and checks that it exports the entry function the
+// loader calls, which is only required for binaries
+fn check_interface(
+    target: &str,
+    package_type: &PackageType,
+    wasm_out: &Path,
+    options: &Options,
+) -> Result<validate::Interface, Error> {
+    let interface = match validate::validate_file(wasm_out) {
+        Ok(interface) => interface,
+        Err(e) => {
+            println!(
+                "wasm-bin: Generated wasm module for target '{}' is invalid: {:?}",
+                target, e
+            );
+            return Err(Error::ValidateError(e));
+        }
+    };
+    println!(
+        "wasm-bin: Target '{}' exports {} and imports {} items",
+        target,
+        interface.exports.len(),
+        interface.imports.len()
+    );
+    if options.verbose {
+        for export in &interface.exports {
+            println!("wasm-bin:   export {} {}", export.kind.name(), export.name);
+        }
+        for import in &interface.imports {
+            println!(
+                "wasm-bin:   import {} {}.{}",
+                import.kind.name(),
+                import.module,
+                import.name
+            );
+        }
+    }
+
+    let entry = match options.entry.function {
+        Some(ref entry) => entry,
+        None => return Ok(interface),
+    };
+    if let Err(e) = interface.check_entry(entry) {
+        let exported = interface.exported_functions();
+        let exported = if exported.is_empty() {
+            "none".to_string()
+        } else {
+            exported.join(", ")
+        };
+        match *package_type {
+            PackageType::Binary => {
+                println!(
+                    "wasm-bin: Target '{}' does not export the entry function '{}' (exported functions: {}). \
+                     Export it with #[wasm_bindgen], pick another one with --entry or pass --no-entry",
+                    target, entry, exported
+                );
+                return Err(Error::ValidateError(e));
+            }
+            PackageType::Library => println!(
+                "wasm-bin: Warning: library target '{}' does not export the entry function '{}'",
+                target, entry
+            ),
+        }
+    }
+    Ok(interface)
+}
+
+// Warns about the functions the bindings of a target expect the page to define, and writes
+// placeholders for them if requested. Returns the path of the written stubs.
+fn check_host_imports(
+    target: &str,
+    js_out: &Path,
+    interface: &validate::Interface,
+    options: &Options,
+) -> Result<Option<PathBuf>, Error> {
+    let js = fs::read_to_string(js_out).map_err(Error::ReadBindingsError)?;
+    let functions = imports::host_functions(&js, &interface.imports);
+    let unresolved = imports::unresolved(&functions);
+    let stubs_out = js_out.with_file_name(imports::stubs_file_name(target));
+    if !unresolved.is_empty() {
+        println!(
+            "wasm-bin: Warning: target '{}' imports functions the page has to define globally: {}",
+            target,
+            unresolved.join(", ")
+        );
+        if options.import_stubs {
+            println!(
+                "wasm-bin: Write placeholders for them to {}",
+                stubs_out.display()
+            );
+            fs::write(&stubs_out, imports::stubs(target, &unresolved))
+                .map_err(Error::WriteImportStubsError)?;
+            return Ok(Some(stubs_out));
+        }
+        println!("wasm-bin: Pass --import-stubs to generate placeholders for them");
+    }
+
+    // Don't let the server load stubs left over from a previous build
+    if let Err(e) = fs::remove_file(&stubs_out) {
+        if e.kind() != io::ErrorKind::NotFound {
+            return Err(Error::RemoveImportStubsError(e));
+        }
+    }
+    Ok(None)
+}
@@ -12,12 +12,16 @@ const LIB_TARGET_KIND_ID: &str = "cdylib";
 #[derive(Debug)]
 pub enum Error {
     RunCommandError(io::Error),
-    CompileErrors,
+    /// Diagnostics of the errors that failed the build
+    CompileErrors(Vec<Diagnostic>),
     DeserializeOutputError,
     CaptureStdoutError,
     StdoutLineError(io::Error),
-    SerializeMessageError(serde_json::error::Error),
     UnexpectedFileCountError,
+    MetadataFailed,
+    LocateProjectFailed,
+    DeserializeMetadataError(serde_json::Error),
+    UnknownPackageError(String),
 }
 
 #[derive(Default)]
@@ -32,6 +36,7 @@ pub struct BuildOptions {
     pub example: Option<String>,
     pub all_targets: bool,
     pub release: bool,
+    pub profile: Option<String>,
     pub features: Option<String>,
     pub all_features: bool,
     pub no_default_features: bool,
@@ -56,6 +61,10 @@ impl<'de> de::Deserialize<'de> for PackageId {
         D: de::Deserializer<'de>,
     {
         let string = String::deserialize(d)?;
+        if !string.contains(' ') {
+            return parse_package_id_spec(&string)
+                .ok_or_else(|| de::Error::custom("invalid PackageId"));
+        }
         let mut s = string.splitn(3, ' ');
         Ok(PackageId {
             name: s.next().unwrap().to_string(),
@@ -71,6 +80,23 @@ impl<'de> de::Deserialize<'de> for PackageId {
     }
 }
 
+// Parses the package id spec format used by newer cargo versions, e.g.
+// `registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0`, or
+// `path+file:///path/to/package#0.1.0` when the name matches the last path segment
+fn parse_package_id_spec(spec: &str) -> Option<PackageId> {
+    let hash = spec.rfind('#')?;
+    let (source_id, fragment) = (&spec[..hash], &spec[hash + 1..]);
+    let (name, version) = match fragment.find('@') {
+        Some(at) => (&fragment[..at], &fragment[at + 1..]),
+        None => (source_id.rsplit('/').next()?, fragment),
+    };
+    Some(PackageId {
+        name: name.to_string(),
+        version: version.to_string(),
+        source_id: source_id.to_string(),
+    })
+}
+
 #[derive(Debug, Deserialize)]
 struct Target {
     kind: Vec<String>,
@@ -80,12 +106,23 @@ struct Target {
 }
 
 #[derive(Debug, Deserialize)]
-struct Profile {
-    opt_level: String,
-    debuginfo: Option<u32>,
-    debug_assertions: bool,
-    overflow_checks: bool,
-    test: bool,
+struct MetadataPackage {
+    name: String,
+    targets: Vec<Target>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Profile {
+    pub opt_level: String,
+    pub debuginfo: Option<u32>,
+    pub debug_assertions: bool,
+    pub overflow_checks: bool,
+    pub test: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -98,56 +135,51 @@ struct Artifact {
     fresh: bool,
 }
 
-#[allow(unused)]
-#[derive(Clone, Deserialize, Serialize)]
-struct DiagnosticCode {
-    code: String,
-    explanation: Option<String>,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DiagnosticCode {
+    pub code: String,
+    pub explanation: Option<String>,
 }
 
-#[allow(unused)]
-#[derive(Clone, Deserialize, Serialize)]
-struct DiagnosticSpanLine {
-    text: String,
-    highlight_start: usize,
-    highlight_end: usize,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DiagnosticSpanLine {
+    pub text: String,
+    pub highlight_start: usize,
+    pub highlight_end: usize,
 }
 
-#[allow(unused)]
-#[derive(Clone, Deserialize, Serialize)]
-struct DiagnosticSpanMacroExpansion {
-    span: DiagnosticSpan,
-    macro_decl_name: String,
-    def_site_span: Option<DiagnosticSpan>,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DiagnosticSpanMacroExpansion {
+    pub span: DiagnosticSpan,
+    pub macro_decl_name: String,
+    pub def_site_span: Option<DiagnosticSpan>,
 }
 
-#[allow(unused)]
-#[derive(Clone, Deserialize, Serialize)]
-struct DiagnosticSpan {
-    file_name: String,
-    byte_start: u32,
-    byte_end: u32,
-    line_start: usize,
-    line_end: usize,
-    column_start: usize,
-    column_end: usize,
-    is_primary: bool,
-    text: Vec<DiagnosticSpanLine>,
-    label: Option<String>,
-    suggested_replacement: Option<String>,
-    expansion: Option<Box<DiagnosticSpanMacroExpansion>>,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DiagnosticSpan {
+    pub file_name: String,
+    pub byte_start: u32,
+    pub byte_end: u32,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub column_start: usize,
+    pub column_end: usize,
+    pub is_primary: bool,
+    pub text: Vec<DiagnosticSpanLine>,
+    pub label: Option<String>,
+    pub suggested_replacement: Option<String>,
+    pub expansion: Option<Box<DiagnosticSpanMacroExpansion>>,
 }
 
-#[allow(unused)]
-#[derive(Clone, Deserialize, Serialize)]
-struct Diagnostic {
-    message: String,
-    code: Option<DiagnosticCode>,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub code: Option<DiagnosticCode>,
     /// "error: internal compiler error", "error", "warning", "note", "help".
-    level: String,
-    spans: Vec<DiagnosticSpan>,
-    children: Vec<Diagnostic>,
-    rendered: Option<String>,
+    pub level: String,
+    pub spans: Vec<DiagnosticSpan>,
+    pub children: Vec<Diagnostic>,
+    pub rendered: Option<String>,
 }
 
 #[allow(unused)]
@@ -168,10 +200,17 @@ struct BuildScript {
     pub env: Vec<(String, String)>,
 }
 
+#[allow(unused)]
+#[derive(Deserialize)]
+struct BuildFinished {
+    success: bool,
+}
+
 enum CargoBuildOutput {
     FromCompiler(CargoFromCompiler),
     Artifact(Artifact),
     BuildScript(BuildScript),
+    BuildFinished,
 }
 
 fn parse_cargo_output(line: &str) -> Result<CargoBuildOutput, (Error)> {
@@ -184,14 +223,17 @@ fn parse_cargo_output(line: &str) -> Result<CargoBuildOutput, (Error)> {
     if let Ok(script_result) = serde_json::from_str::<BuildScript>(line) {
         return Ok(CargoBuildOutput::BuildScript(script_result));
     }
+    if serde_json::from_str::<BuildFinished>(line).is_ok() {
+        return Ok(CargoBuildOutput::BuildFinished);
+    }
 
     Err(Error::DeserializeOutputError)
 }
 
 #[derive(Clone)]
 pub enum WasmArtifact {
-    Binary(String, PathBuf),
-    Library(String, PathBuf),
+    Binary(String, PathBuf, Profile),
+    Library(String, PathBuf, Profile),
 }
 
 // Returns a list of paths to binary wasm artifacts produced by the cargo build command
@@ -200,7 +242,7 @@ pub fn build(options: &BuildOptions) -> Result<Vec<WasmArtifact>, Error> {
     cmd.stdout(Stdio::piped())
         .arg("build")
         .arg("--target=wasm32-unknown-unknown")
-        .args(&["--message-format", "json"]);
+        .args(["--message-format", "json"]);
 
     if let Some(ref package) = options.package {
         cmd.arg("--package").arg(package);
@@ -232,6 +274,9 @@ pub fn build(options: &BuildOptions) -> Result<Vec<WasmArtifact>, Error> {
     if options.release {
         cmd.arg("--release");
     }
+    if let Some(ref profile) = options.profile {
+        cmd.arg("--profile").arg(profile);
+    }
     if let Some(ref features) = options.features {
         cmd.arg("--features").arg(features);
     }
@@ -279,42 +324,95 @@ pub fn build(options: &BuildOptions) -> Result<Vec<WasmArtifact>, Error> {
         };
         match output {
             CargoBuildOutput::FromCompiler(from_compiler) => {
-                match from_compiler.message.level.as_str() {
-                    "error" => {
-                        let error = from_compiler.message.clone().rendered.map_or(
-                            serde_json::to_string(&from_compiler.message)
-                                .map_err(Error::SerializeMessageError)?,
-                            |v| v.to_string(),
-                        );
-                        errors.push(error);
-                    }
-                    _ => {}
+                // Cargo doesn't print compiler messages when they are requested as json
+                if let Some(ref rendered) = from_compiler.message.rendered {
+                    print!("{}", rendered);
+                }
+                if from_compiler.message.level == "error" {
+                    errors.push(from_compiler.message);
                 }
             }
-            CargoBuildOutput::Artifact(mut artifact) => {
-                if artifact.filenames.len() != 1 {
-                    return Err(Error::UnexpectedFileCountError);
+            CargoBuildOutput::Artifact(artifact) => {
+                let is_binary = artifact.target.kind.contains(&bin_id)
+                    || artifact.target.kind.contains(&example_id);
+                if !is_binary && !artifact.target.kind.contains(&lib_id) {
+                    // Dependencies, build scripts and rlibs, which may have several files
+                    continue;
                 }
-                if artifact.target.kind.contains(&bin_id)
-                    || artifact.target.kind.contains(&example_id)
-                {
-                    artifacts.push(WasmArtifact::Binary(
-                        artifact.target.name,
-                        artifact.filenames.pop().unwrap(),
-                    ));
-                } else if artifact.target.kind.contains(&lib_id) {
-                    artifacts.push(WasmArtifact::Library(
-                        artifact.target.name,
-                        artifact.filenames.pop().unwrap(),
-                    ));
+                let mut modules: Vec<PathBuf> = artifact
+                    .filenames
+                    .into_iter()
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+                    .collect();
+                if modules.len() != 1 {
+                    return Err(Error::UnexpectedFileCountError);
                 }
+                let module = modules.pop().unwrap();
+                artifacts.push(if is_binary {
+                    WasmArtifact::Binary(artifact.target.name, module, artifact.profile)
+                } else {
+                    WasmArtifact::Library(artifact.target.name, module, artifact.profile)
+                });
             }
-            CargoBuildOutput::BuildScript(_) => {}
+            CargoBuildOutput::BuildScript(_) | CargoBuildOutput::BuildFinished => {}
         }
     }
 
     match errors.len() {
         0 => Ok(artifacts),
-        _ => Err(Error::CompileErrors),
+        _ => Err(Error::CompileErrors(errors)),
+    }
+}
+
+/// Names of the wasm-bin targets (binaries, examples and cdylib libraries) of a workspace package
+pub fn package_targets(package: &str, manifest_path: Option<&str>) -> Result<Vec<String>, Error> {
+    let mut cmd = Command::new("cargo");
+    cmd.args(["metadata", "--format-version", "1", "--no-deps"]);
+    if let Some(manifest_path) = manifest_path {
+        cmd.arg("--manifest-path").arg(manifest_path);
+    }
+    let output = cmd
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(Error::RunCommandError)?;
+    if !output.status.success() {
+        return Err(Error::MetadataFailed);
+    }
+    let metadata: Metadata =
+        serde_json::from_slice(&output.stdout).map_err(Error::DeserializeMetadataError)?;
+    let package = metadata
+        .packages
+        .into_iter()
+        .find(|p| p.name == package)
+        .ok_or_else(|| Error::UnknownPackageError(package.to_string()))?;
+    Ok(package
+        .targets
+        .into_iter()
+        .filter(|target| {
+            target.kind.iter().any(|kind| {
+                kind == BIN_TARGET_KIND_ID
+                    || kind == EXAMPLE_TARGET_KIND_ID
+                    || kind == LIB_TARGET_KIND_ID
+            })
+        })
+        .map(|target| target.name)
+        .collect())
+}
+
+/// Root directory of the workspace of a package, where its Cargo.lock is
+pub fn workspace_root(manifest_path: Option<&str>) -> Result<PathBuf, Error> {
+    let mut cmd = Command::new("cargo");
+    cmd.args(["locate-project", "--workspace", "--message-format", "plain"]);
+    if let Some(manifest_path) = manifest_path {
+        cmd.arg("--manifest-path").arg(manifest_path);
+    }
+    let output = cmd
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(Error::RunCommandError)?;
+    if !output.status.success() {
+        return Err(Error::LocateProjectFailed);
     }
+    let manifest = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    Ok(manifest.parent().map(PathBuf::from).unwrap_or_default())
 }
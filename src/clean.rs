@@ -0,0 +1,191 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use build;
+use cargo;
+use util::format_size;
+
+// Caches of the dev server, such as compressed responses
+const CACHE_DIR: &str = "cache";
+// Kept, since browsers may have been told to trust the certificate
+const CERT_DIR: &str = "certs";
+
+#[derive(Debug)]
+pub enum Error {
+    ReadDirError(io::Error),
+    RemoveError(io::Error),
+    CargoMetadataError(cargo::Error),
+    LocateWorkspaceError(cargo::Error),
+    /// A profile or target name that isn't a single directory name, such as `..`
+    InvalidNameError(String),
+    /// A path that would be removed but isn't inside the outputs directory
+    OutsideOutputsError(PathBuf),
+}
+
+#[derive(Default)]
+pub struct Options {
+    pub package: Option<String>,
+    pub bin: Option<String>,
+    pub example: Option<String>,
+    pub manifest_path: Option<String>,
+    /// Only remove the outputs of this profile directory, e.g. `release`
+    pub profile_dir: Option<String>,
+    /// List what would be removed without removing anything
+    pub dry_run: bool,
+}
+
+/// A file or directory to remove
+pub struct Removal {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+fn size(path: &Path) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += self::size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+// Profile and target names are joined onto the outputs directory, so they have to name a
+// directory inside it
+fn check_name(name: &str) -> Result<&str, Error> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !name.contains(['/', '\\']) => Ok(name),
+        _ => Err(Error::InvalidNameError(name.to_string())),
+    }
+}
+
+// Refuses to remove anything outside of the outputs directory, also through symlinks
+fn check_inside(root: &Path, path: &Path) -> Result<(), Error> {
+    let inside = path.strip_prefix(root).is_ok_and(|rest| {
+        rest.components().next().is_some()
+            && rest
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+    });
+    let resolved_inside = match (path.canonicalize(), root.canonicalize()) {
+        (Ok(path), Ok(root)) => path.starts_with(&root) && path != root,
+        _ => true,
+    };
+    if inside && resolved_inside {
+        Ok(())
+    } else {
+        Err(Error::OutsideOutputsError(path.to_path_buf()))
+    }
+}
+
+fn profile_dirs(root: &Path, options: &Options) -> Result<Vec<PathBuf>, Error> {
+    if let Some(ref profile_dir) = options.profile_dir {
+        return Ok(vec![root.join(check_name(profile_dir)?)]);
+    }
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::ReadDirError(e)),
+    };
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(Error::ReadDirError)?;
+        let name = entry.file_name();
+        if name == CACHE_DIR || name == CERT_DIR {
+            continue;
+        }
+        if entry.file_type().map_err(Error::ReadDirError)?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Outputs of the selected targets and profiles in the workspace, all of them by default. The
+/// caches of the dev server are only included when nothing is selected.
+pub fn removals(options: &Options) -> Result<Vec<Removal>, Error> {
+    let root = build::output_root(options.manifest_path.as_deref())
+        .map_err(Error::LocateWorkspaceError)?;
+    let mut targets: Vec<String> = options
+        .bin
+        .iter()
+        .chain(options.example.iter())
+        .cloned()
+        .collect();
+    if let Some(ref package) = options.package {
+        let package_targets = cargo::package_targets(package, options.manifest_path.as_deref())
+            .map_err(Error::CargoMetadataError)?;
+        targets.extend(package_targets);
+    }
+    let select_targets =
+        options.bin.is_some() || options.example.is_some() || options.package.is_some();
+
+    for target in &targets {
+        check_name(target)?;
+    }
+
+    let mut paths = Vec::new();
+    for profile_dir in profile_dirs(&root, options)? {
+        if select_targets {
+            paths.extend(targets.iter().map(|target| profile_dir.join(target)));
+        } else {
+            paths.push(profile_dir);
+        }
+    }
+    // Cache entries are named by hash and can't be told apart by target or profile
+    if !select_targets && options.profile_dir.is_none() {
+        paths.push(root.join(CACHE_DIR));
+    }
+
+    let mut removals = Vec::new();
+    for path in paths {
+        check_inside(&root, &path)?;
+        match size(&path) {
+            Ok(size) => removals.push(Removal { path, size }),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::ReadDirError(e)),
+        }
+    }
+    Ok(removals)
+}
+
+/// Removes the files and directories generated by wasm-bin that the options select
+pub fn clean(options: &Options) -> Result<Vec<Removal>, Error> {
+    let removals = removals(options)?;
+    let mut total = 0;
+    for removal in &removals {
+        total += removal.size;
+        if options.dry_run {
+            println!(
+                "wasm-bin: Would remove {} ({})",
+                removal.path.display(),
+                format_size(removal.size)
+            );
+            continue;
+        }
+        println!(
+            "wasm-bin: Remove {} ({})",
+            removal.path.display(),
+            format_size(removal.size)
+        );
+        let result = if removal.path.is_dir() {
+            fs::remove_dir_all(&removal.path)
+        } else {
+            fs::remove_file(&removal.path)
+        };
+        result.map_err(Error::RemoveError)?;
+    }
+    if removals.is_empty() {
+        println!("wasm-bin: Nothing to clean");
+    } else if options.dry_run {
+        println!("wasm-bin: Would free {}", format_size(total));
+    } else {
+        println!("wasm-bin: Freed {}", format_size(total));
+    }
+    Ok(removals)
+}
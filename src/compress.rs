@@ -0,0 +1,205 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use brotli;
+use flate2;
+use flate2::write::GzEncoder;
+
+const CACHE_DIR: &str = "./target/wasm-bin/cache/compress";
+const COMPRESSIBLE_EXTENSIONS: &[&str] = &[
+    "wasm", "js", "mjs", "html", "css", "json", "map", "svg", "txt", "xml",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+/// Compression effort, `Best` for distributed files and `Fast` for compressing on the fly
+#[derive(Clone, Copy, Debug)]
+pub enum Level {
+    Fast,
+    Best,
+}
+
+impl Encoding {
+    /// Name of the encoding in `Accept-Encoding` and `Content-Encoding` headers
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// File extension of precompressed files
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+
+    /// Path of the precompressed sibling of a file
+    pub fn sibling(&self, path: &Path) -> PathBuf {
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(self.extension());
+        path.with_file_name(file_name)
+    }
+}
+
+/// Picks the preferred encoding accepted by an `Accept-Encoding` header value
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut qualities: Vec<(String, f32)> = Vec::new();
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality = parts
+            .filter_map(|param| {
+                param
+                    .trim()
+                    .strip_prefix("q=")
+                    .and_then(|quality| quality.trim().parse::<f32>().ok())
+            })
+            .next()
+            .unwrap_or(1.0);
+        let coding = match coding.as_str() {
+            "x-gzip" => "gzip".to_string(),
+            _ => coding,
+        };
+        qualities.push((coding, quality));
+    }
+    let quality_of = |coding: &str| {
+        qualities
+            .iter()
+            .find(|(name, _)| name == coding)
+            .map(|&(_, quality)| quality)
+    };
+
+    let mut best: Option<(Encoding, f32)> = None;
+    // Brotli comes first, so it wins ties
+    for &encoding in &[Encoding::Brotli, Encoding::Gzip] {
+        // `*` only covers codings that aren't listed, so `br;q=0, *` still refuses brotli
+        let quality = match quality_of(encoding.name()).or_else(|| quality_of("*")) {
+            Some(quality) if quality > 0.0 => quality,
+            _ => continue,
+        };
+        best = match best {
+            Some((_, best_quality)) if best_quality >= quality => best,
+            _ => Some((encoding, quality)),
+        };
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+pub fn compress(data: &[u8], encoding: Encoding, level: Level) -> io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Brotli => {
+            let quality = match level {
+                Level::Fast => 5,
+                Level::Best => 11,
+            };
+            let mut out = Vec::new();
+            {
+                let mut writer = brotli::CompressorWriter::new(&mut out, 4096, quality, 22);
+                writer.write_all(data)?;
+            }
+            Ok(out)
+        }
+        Encoding::Gzip => {
+            let compression = match level {
+                Level::Fast => flate2::Compression::default(),
+                Level::Best => flate2::Compression::best(),
+            };
+            let mut encoder = GzEncoder::new(Vec::new(), compression);
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+    }
+}
+
+/// Whether a file is worth compressing, judging by its extension
+pub fn is_compressible(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| COMPRESSIBLE_EXTENSIONS.contains(&extension))
+        .unwrap_or(false)
+}
+
+/// Writes `.gz` and `.br` siblings of a file
+pub fn precompress(path: &Path) -> io::Result<()> {
+    let contents = fs::read(path)?;
+    for encoding in &[Encoding::Brotli, Encoding::Gzip] {
+        let compressed = compress(&contents, *encoding, Level::Best)?;
+        fs::write(encoding.sibling(path), compressed)?;
+    }
+    Ok(())
+}
+
+/// Returns a precompressed sibling of a file if there is one at least as new as the file
+pub fn precompressed(path: &Path, encoding: Encoding) -> Option<PathBuf> {
+    let sibling = encoding.sibling(path);
+    if is_up_to_date(&sibling, path) {
+        Some(sibling)
+    } else {
+        None
+    }
+}
+
+/// Compresses a file, reusing the result from the on-disk cache while the file is unmodified
+pub fn compress_cached(path: &Path, encoding: Encoding) -> io::Result<Vec<u8>> {
+    let mut hasher = DefaultHasher::new();
+    path.canonicalize()?.hash(&mut hasher);
+    let mut cache_path = PathBuf::from(CACHE_DIR);
+    cache_path.push(format!("{:016x}.{}", hasher.finish(), encoding.extension()));
+
+    if is_up_to_date(&cache_path, path) {
+        return fs::read(&cache_path);
+    }
+    let compressed = compress(&fs::read(path)?, encoding, Level::Fast)?;
+    fs::create_dir_all(CACHE_DIR)?;
+    fs::write(&cache_path, &compressed)?;
+    Ok(compressed)
+}
+
+// Whether `derived` exists and was modified no earlier than `source`
+fn is_up_to_date(derived: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(derived), modified(source)) {
+        (Ok(derived), Ok(source)) => derived >= source,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate_prefers_brotli() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+    }
+
+    #[test]
+    fn negotiate_respects_quality() {
+        assert_eq!(negotiate("br;q=0.5, gzip;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+    }
+
+    #[test]
+    fn negotiate_wildcard_excludes_refused_codings() {
+        assert_eq!(negotiate("br;q=0, *"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*, gzip;q=0"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("br;q=0, gzip;q=0, *"), None);
+        assert_eq!(negotiate("x-gzip, *;q=0"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*;q=0"), None);
+    }
+}
@@ -0,0 +1,17 @@
+/// Path the console forwarding script posts messages to
+pub const ENDPOINT: &str = "/__wasm-bin/console";
+
+/// Console output or an uncaught error of a page
+#[derive(Deserialize)]
+pub struct ConsoleMessage {
+    pub target: String,
+    pub level: String,
+    pub message: String,
+}
+
+/// Prints a message to the terminal, prefixing every line with its target and level
+pub fn print(message: &ConsoleMessage) {
+    for line in message.message.lines() {
+        println!("[{}] {}: {}", message.target, message.level, line);
+    }
+}
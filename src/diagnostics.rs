@@ -0,0 +1,117 @@
+use build;
+use cargo;
+use cargo::{Diagnostic, DiagnosticSpan, DiagnosticSpanLine};
+use util::escape_html;
+
+/// Renders an html page describing why a build failed, with the compiler diagnostics of
+/// compile errors
+pub fn render(error: &build::Error) -> String {
+    let details = match *error {
+        build::Error::CargoBuildError(cargo::Error::CompileErrors(ref diagnostics)) => {
+            diagnostics.iter().map(render_diagnostic).collect()
+        }
+        build::Error::BindgenError(ref error) if error.output().is_some() => {
+            let output = error.output().unwrap();
+            format!(
+                "<p>wasm-bindgen failed</p>\n<pre>{}{}</pre>\n",
+                escape_html(&output.stdout),
+                escape_html(&output.stderr)
+            )
+        }
+        ref error => format!("<pre>{}</pre>\n", escape_html(&format!("{:?}", error))),
+    };
+    format!(
+        r#"<html>
+    <head>
+        <meta content="text/html;charset=utf-8" http-equiv="Content-Type"/>
+        <title>Build failed</title>
+        <style>
+            body {{ font-family: sans-serif; margin: 2em; }}
+            pre {{ background: #f4f4f4; padding: 0.5em; overflow-x: auto; }}
+            mark {{ background: #ffd0d0; }}
+            .location {{ font-family: monospace; color: #555; }}
+            .line-number {{ color: #999; }}
+        </style>
+    </head>
+    <body>
+        <h1>Build failed</h1>
+{details}    </body>
+</html>
+"#,
+        details = details
+    )
+}
+
+fn render_diagnostic(diagnostic: &Diagnostic) -> String {
+    let code = match diagnostic.code {
+        Some(ref code) => format!("[{}]", escape_html(&code.code)),
+        None => String::new(),
+    };
+    let mut html = format!(
+        "<h2>{}{}: {}</h2>\n",
+        escape_html(&diagnostic.level),
+        code,
+        escape_html(&diagnostic.message)
+    );
+    for span in &diagnostic.spans {
+        html.push_str(&render_span(span));
+    }
+    for child in &diagnostic.children {
+        html.push_str(&format!(
+            "<p><b>{}</b>: {}</p>\n",
+            escape_html(&child.level),
+            escape_html(&child.message)
+        ));
+        for span in &child.spans {
+            html.push_str(&render_span(span));
+        }
+    }
+    html
+}
+
+fn render_span(span: &DiagnosticSpan) -> String {
+    let width = (span.line_start + span.text.len()).to_string().len();
+    let mut snippet = String::new();
+    for (i, line) in span.text.iter().enumerate() {
+        snippet.push_str(&format!(
+            "<span class=\"line-number\">{:>width$} |</span> {}\n",
+            span.line_start + i,
+            highlight(line),
+            width = width
+        ));
+    }
+    let mut html = format!(
+        "<p class=\"location\">{}:{}:{}</p>\n",
+        escape_html(&span.file_name),
+        span.line_start,
+        span.column_start
+    );
+    if !snippet.is_empty() {
+        html.push_str(&format!("<pre>{}</pre>\n", snippet));
+    }
+    if let Some(ref label) = span.label {
+        html.push_str(&format!("<p>{}</p>\n", escape_html(label)));
+    }
+    html
+}
+
+// Escapes a line of source code and marks its highlighted columns, which are 1-based
+fn highlight(line: &DiagnosticSpanLine) -> String {
+    let chars: Vec<char> = line.text.chars().collect();
+    let start = line.highlight_start.saturating_sub(1).min(chars.len());
+    let end = line
+        .highlight_end
+        .saturating_sub(1)
+        .min(chars.len())
+        .max(start);
+    let part = |from: usize, to: usize| escape_html(&chars[from..to].iter().collect::<String>());
+    if start == end {
+        return part(0, chars.len());
+    }
+    format!(
+        "{}<mark>{}</mark>{}",
+        part(0, start),
+        part(start, end),
+        part(end, chars.len())
+    )
+}
@@ -0,0 +1,71 @@
+use std::str::FromStr;
+
+use glob::Pattern;
+use hyper::Response;
+
+/// A response header added to every response whose request path matches a glob pattern.
+///
+/// Parsed from `[GLOB=]NAME: VALUE`, where the pattern defaults to `*`, matching every path.
+#[derive(Clone, Debug)]
+pub struct HeaderRule {
+    pub pattern: Pattern,
+    pub name: String,
+    pub value: String,
+}
+
+impl HeaderRule {
+    fn all(name: &str, value: &str) -> HeaderRule {
+        HeaderRule {
+            pattern: Pattern::new("*").unwrap(),
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+impl FromStr for HeaderRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<HeaderRule, String> {
+        let colon = s
+            .find(':')
+            .ok_or_else(|| format!("expected `[GLOB=]NAME: VALUE`, got `{}`", s))?;
+        let (pattern, name) = match s[..colon].find('=') {
+            Some(eq) => (&s[..eq], &s[eq + 1..colon]),
+            None => ("*", &s[..colon]),
+        };
+        let name = name.trim();
+        if name.is_empty() || !name.bytes().all(is_token_byte) {
+            return Err(format!("invalid header name `{}`", name));
+        }
+        Ok(HeaderRule {
+            pattern: Pattern::new(pattern.trim())
+                .map_err(|e| format!("invalid glob `{}`: {}", pattern, e))?,
+            name: name.to_string(),
+            value: s[colon + 1..].trim().to_string(),
+        })
+    }
+}
+
+// Characters allowed in a header field name (RFC 7230 tchar)
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Headers required for `SharedArrayBuffer` and wasm threads
+pub fn cross_origin_isolation() -> Vec<HeaderRule> {
+    vec![
+        HeaderRule::all("Cross-Origin-Opener-Policy", "same-origin"),
+        HeaderRule::all("Cross-Origin-Embedder-Policy", "require-corp"),
+    ]
+}
+
+/// Sets the headers of every rule matching the request path, later rules take precedence
+pub fn apply(rules: &[HeaderRule], path: &str, mut response: Response) -> Response {
+    for rule in rules.iter().filter(|rule| rule.pattern.matches(path)) {
+        response
+            .headers_mut()
+            .set_raw(rule.name.clone(), rule.value.clone());
+    }
+    response
+}
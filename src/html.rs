@@ -0,0 +1,340 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde_json;
+
+use console;
+use imports;
+
+const HTML_DIR: &str = "./html";
+
+// The built-in page used when no ./html/<target>.html template exists
+const DEFAULT_TEMPLATE: &str = r#"<html>
+    <head>
+        <meta content="text/html;charset=utf-8" http-equiv="Content-Type"/>
+        {{loader}}
+    </head>
+    <body>
+    </body>
+</html>
+"#;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadTemplateError(io::Error),
+}
+
+/// Argument passed to the entry function by the loader
+#[derive(Clone, Debug)]
+pub enum EntryArgs {
+    None,
+    /// The page's query string, without the leading `?`
+    Query,
+    /// A JSON value, passed to the entry function as a JSON string
+    Json(serde_json::Value),
+}
+
+/// Exported function the loader calls once the wasm module is instantiated
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// Name of the export, `None` if nothing should be called (e.g. for `#[wasm_bindgen(start)]`)
+    pub function: Option<String>,
+    pub args: EntryArgs,
+}
+
+impl Default for Entry {
+    fn default() -> Entry {
+        Entry {
+            function: Some("main".to_string()),
+            args: EntryArgs::None,
+        }
+    }
+}
+
+/// Values available to placeholders in an html template.
+///
+/// Supported placeholders are `{{target}}`, `{{js}}`, `{{wasm}}` and `{{loader}}`.
+/// Anything else between double braces is left untouched.
+pub struct Context {
+    pub target: String,
+    pub entry: Entry,
+    /// Prefix of the paths to the generated files, `./` by default
+    pub base: String,
+    /// Load the stubs generated for the functions the target imports from the page
+    pub import_stubs: bool,
+}
+
+impl Context {
+    pub fn new(target: &str) -> Context {
+        Context {
+            target: target.to_string(),
+            entry: Entry::default(),
+            base: "./".to_string(),
+            import_stubs: false,
+        }
+    }
+
+    // Script run after the module is instantiated. Exports generated by wasm-bindgen are
+    // preferred, falling back to raw exports of the wasm module.
+    fn entry_call(&self) -> String {
+        let function = match self.entry.function {
+            Some(ref function) => script_string(function),
+            None => return String::new(),
+        };
+        let args = match self.entry.args {
+            EntryArgs::None => String::new(),
+            EntryArgs::Query => "window.location.search.replace(/^\\?/, '')".to_string(),
+            EntryArgs::Json(ref value) => script_string(&value.to_string()),
+        };
+        format!(
+            r#"
+                    var entry = wasm_bindgen[{function}] || wasm_bindgen.wasm[{function}];
+                    if (typeof entry !== 'function') {{
+                        throw new Error("wasm-bin: entry function " + {function} + " is not exported");
+                    }}
+                    entry({args});"#,
+            function = function,
+            args = args
+        )
+    }
+
+    fn js(&self) -> String {
+        format!("{}{}.js", self.base, self.target)
+    }
+
+    fn wasm(&self) -> String {
+        format!("{}{}_bg.wasm", self.base, self.target)
+    }
+
+    fn loader(&self) -> String {
+        let stubs = if self.import_stubs {
+            format!(
+                "<script src='{}{}'></script>\n        ",
+                self.base,
+                imports::stubs_file_name(&self.target)
+            )
+        } else {
+            String::new()
+        };
+        format!(
+            r#"{stubs}<script src='{js}'></script>
+        <script>
+            window.addEventListener('load', function() {{
+                wasm_bindgen("{wasm}").then(function() {{{entry_call}
+                }});
+            }}, false);
+        </script>"#,
+            stubs = stubs,
+            js = self.js(),
+            wasm = self.wasm(),
+            entry_call = self.entry_call()
+        )
+    }
+
+    fn value(&self, placeholder: &str) -> Option<String> {
+        match placeholder {
+            "target" => Some(self.target.clone()),
+            "js" => Some(self.js()),
+            "wasm" => Some(self.wasm()),
+            "loader" => Some(self.loader()),
+            _ => None,
+        }
+    }
+}
+
+// Quotes a string as a javascript string literal that is safe to embed in a script tag
+fn script_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap().replace("</", "<\\/")
+}
+
+/// Script forwarding console output, uncaught errors and unhandled rejections of a page to
+/// the console endpoint of the dev server
+pub fn console_script(target: &str) -> String {
+    format!(
+        r#"<script>
+            (function() {{
+                var target = {target};
+                function format(arg) {{
+                    if (arg instanceof Error) {{
+                        return arg.stack || String(arg);
+                    }}
+                    if (typeof arg === 'object' && arg !== null) {{
+                        try {{
+                            return JSON.stringify(arg);
+                        }} catch (e) {{}}
+                    }}
+                    return String(arg);
+                }}
+                function send(level, args) {{
+                    var message = Array.prototype.map.call(args, format).join(' ');
+                    fetch({endpoint}, {{
+                        method: 'POST',
+                        keepalive: true,
+                        body: JSON.stringify({{ target: target, level: level, message: message }})
+                    }}).catch(function() {{}});
+                }}
+                ['log', 'info', 'warn', 'error', 'debug'].forEach(function(level) {{
+                    var original = console[level];
+                    console[level] = function() {{
+                        send(level, arguments);
+                        return original.apply(console, arguments);
+                    }};
+                }});
+                window.addEventListener('error', function(event) {{
+                    send('uncaught', [event.error || event.message]);
+                }});
+                window.addEventListener('unhandledrejection', function(event) {{
+                    send('unhandled rejection', [event.reason]);
+                }});
+            }})();
+        </script>"#,
+        target = script_string(target),
+        endpoint = script_string(console::ENDPOINT)
+    )
+}
+
+/// Inserts html at the start of the `<head>` of a page, so its scripts run before the page's
+/// own scripts
+pub fn inject_head(page: &str, html: &str) -> String {
+    let lowercase = page.to_ascii_lowercase();
+    let position = lowercase
+        .match_indices("<head")
+        .map(|(start, _)| start)
+        .find(|&start| {
+            lowercase[start + 5..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace())
+        })
+        .and_then(|start| lowercase[start..].find('>').map(|end| start + end + 1))
+        .unwrap_or(0);
+    let mut injected = String::with_capacity(page.len() + html.len());
+    injected.push_str(&page[..position]);
+    injected.push_str(html);
+    injected.push_str(&page[position..]);
+    injected
+}
+
+/// The page used for targets without a template
+pub fn default_template() -> &'static str {
+    DEFAULT_TEMPLATE
+}
+
+/// Path of the custom html template for a target
+pub fn template_path(target: &str) -> PathBuf {
+    let mut path = PathBuf::from(HTML_DIR);
+    path.push(format!("{}.html", target));
+    path
+}
+
+/// Reads the custom html template for a target, falling back to the built-in template
+pub fn load_template(target: &str) -> Result<String, Error> {
+    match fs::read_to_string(template_path(target)) {
+        Ok(template) => Ok(template),
+        Err(e) => match e.kind() {
+            io::ErrorKind::NotFound => Ok(DEFAULT_TEMPLATE.to_string()),
+            _ => Err(Error::ReadTemplateError(e)),
+        },
+    }
+}
+
+/// Replaces every known `{{placeholder}}` in the template with its value
+pub fn expand(template: &str, context: &Context) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let end = match after_open.find("}}") {
+            Some(end) => end,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        match context.value(after_open[..end].trim()) {
+            Some(value) => {
+                out.push_str(&value);
+                rest = &after_open[end + 2..];
+            }
+            // Kept as is, a placeholder may start in the middle of it, like in `{{ {{js}}`
+            None => {
+                out.push('{');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Renders the index page for a target from its template
+pub fn render_index(context: &Context) -> Result<String, Error> {
+    let template = load_template(&context.target)?;
+    Ok(expand(&template, context))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn expand_known_placeholders() {
+        let context = Context::new("app");
+        let expanded = expand(
+            "<script src='{{js}}'></script><p>{{ target }}</p>{{wasm}}",
+            &context,
+        );
+        assert_eq!(
+            expanded,
+            "<script src='./app.js'></script><p>app</p>./app_bg.wasm"
+        );
+    }
+
+    #[test]
+    fn expand_leaves_unknown_placeholders() {
+        let context = Context::new("app");
+        assert_eq!(expand("{{unknown}} {{js", &context), "{{unknown}} {{js");
+        assert_eq!(
+            expand("{{ {{js}} {{{wasm}}}", &context),
+            "{{ ./app.js {./app_bg.wasm}"
+        );
+    }
+
+    #[test]
+    fn loader_calls_configured_entry() {
+        let mut context = Context::new("app");
+        context.entry = Entry {
+            function: Some("start".to_string()),
+            args: EntryArgs::Json(serde_json::from_str(r#"{"html": "</script>"}"#).unwrap()),
+        };
+        let loader = expand("{{loader}}", &context);
+        assert!(loader.contains(r#"wasm_bindgen["start"] || wasm_bindgen.wasm["start"]"#));
+        assert!(loader.contains(r#"entry("{\"html\":\"<\/script>\"}");"#));
+
+        context.entry.function = None;
+        assert!(!expand("{{loader}}", &context).contains("entry("));
+    }
+
+    #[test]
+    fn loader_loads_import_stubs_first() {
+        let mut context = Context::new("app");
+        assert!(!expand("{{loader}}", &context).contains("app_imports.js"));
+
+        context.import_stubs = true;
+        let loader = expand("{{loader}}", &context);
+        let stubs = loader.find("<script src='./app_imports.js'>").unwrap();
+        assert!(stubs < loader.find("<script src='./app.js'>").unwrap());
+    }
+
+    #[test]
+    fn inject_at_start_of_head() {
+        assert_eq!(
+            inject_head("<html><header></header><HEAD lang=en><title>", "<script>"),
+            "<html><header></header><HEAD lang=en><script><title>"
+        );
+        assert_eq!(
+            inject_head("<p>no head</p>", "<script>"),
+            "<script><p>no head</p>"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use validate::{Import, Kind};
+
+/// Globals defined by browsers, which imports can use without the page defining them
+const BROWSER_GLOBALS: &[&str] = &[
+    "alert",
+    "atob",
+    "btoa",
+    "cancelAnimationFrame",
+    "clearInterval",
+    "clearTimeout",
+    "confirm",
+    "createImageBitmap",
+    "decodeURI",
+    "decodeURIComponent",
+    "encodeURI",
+    "encodeURIComponent",
+    "fetch",
+    "isFinite",
+    "isNaN",
+    "parseFloat",
+    "parseInt",
+    "print",
+    "prompt",
+    "queueMicrotask",
+    "reportError",
+    "requestAnimationFrame",
+    "requestIdleCallback",
+    "setInterval",
+    "setTimeout",
+    "structuredClone",
+];
+
+/// Name of the stub file generated for a target
+pub fn stubs_file_name(target: &str) -> String {
+    format!("{}_imports.js", target)
+}
+
+// The javascript name of a wasm-bindgen import shim, named `__wbg_<name>_<hash>`
+fn shim_name(import: &str) -> Option<&str> {
+    let rest = import.strip_prefix("__wbg_")?;
+    let (name, hash) = rest.rsplit_once('_')?;
+    if !name.is_empty() && !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+// The body of the shim function defined for an import in the generated bindings
+fn shim_body<'a>(js: &'a str, import: &str) -> Option<&'a str> {
+    let start = js.find(import)? + import.len();
+    let open = start + js[start..].find('{')?;
+    let mut depth = 0;
+    for (i, c) in js[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&js[open + 1..open + i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+// Whether some code calls a global function, as `name(...)` rather than `object.name(...)`
+fn calls_global(code: &str, name: &str) -> bool {
+    code.match_indices(name).any(|(start, _)| {
+        let before = code[..start].chars().next_back();
+        let after = code[start + name.len()..].chars().next();
+        after == Some('(') && !before.is_some_and(|c| is_identifier_char(c) || c == '.')
+    })
+}
+
+/// Global functions the generated bindings expect the page to define, e.g. for
+/// `#[wasm_bindgen] extern { fn add(l: i32, r: i32) -> i32; }`
+pub fn host_functions(js: &str, imports: &[Import]) -> Vec<String> {
+    let mut functions: Vec<String> = imports
+        .iter()
+        .filter(|import| import.kind == Kind::Function)
+        .filter_map(|import| {
+            let name = shim_name(&import.name)?;
+            let body = shim_body(js, &import.name)?;
+            if calls_global(body, name) {
+                Some(name.to_string())
+            } else {
+                None
+            }
+        })
+        .collect();
+    functions.sort();
+    functions.dedup();
+    functions
+}
+
+/// Host functions that browsers don't provide, so the page has to
+pub fn unresolved(functions: &[String]) -> Vec<String> {
+    functions
+        .iter()
+        .filter(|function| !BROWSER_GLOBALS.contains(&function.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Script defining placeholders for host functions the page doesn't define, warning when
+/// they are called
+pub fn stubs(target: &str, functions: &[String]) -> String {
+    let mut script = format!(
+        "// Placeholders for the functions target '{}' imports from the page, generated by wasm-bin.\n\
+         // Define them in your page to replace these.\n\
+         (function() {{\n    \
+             function stub(name) {{\n        \
+                 return function() {{\n            \
+                     console.warn('wasm-bin: ' + name + ' is not implemented by the page');\n        \
+                 }};\n    \
+             }}\n",
+        target
+    );
+    for function in functions {
+        script.push_str(&format!(
+            "    if (typeof window.{name} !== 'function') {{\n        \
+                 window.{name} = stub('{name}');\n    \
+             }}\n",
+            name = function
+        ));
+    }
+    script.push_str("})();\n");
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BINDINGS: &str = r#"
+        imports.wbg.__wbg_add_9d7a2c1f3b8e4a60 = function(arg0, arg1) {
+            const ret = add(arg0, arg1);
+            return ret;
+        };
+        imports.wbg.__wbg_alert_1b2c3d4e5f607182 = function(arg0, arg1) {
+            alert(getStringFromWasm0(arg0, arg1));
+        };
+        imports.wbg.__wbg_log_0a1b2c3d4e5f6071 = function(arg0) {
+            console.log(getObject(arg0));
+        };
+        imports.wbg.__wbg_render_aa00bb11cc22dd33 = function() { return handleError(function (arg0) {
+            render(getObject(arg0));
+        }, arguments) };
+        imports.wbg.__wbindgen_throw = function(arg0, arg1) {
+            throw new Error(getStringFromWasm0(arg0, arg1));
+        };
+    "#;
+
+    fn import(name: &str) -> Import {
+        Import {
+            module: "wbg".to_string(),
+            name: name.to_string(),
+            kind: Kind::Function,
+        }
+    }
+
+    #[test]
+    fn detect_host_functions() {
+        let imports = vec![
+            import("__wbg_add_9d7a2c1f3b8e4a60"),
+            import("__wbg_alert_1b2c3d4e5f607182"),
+            import("__wbg_log_0a1b2c3d4e5f6071"),
+            import("__wbg_render_aa00bb11cc22dd33"),
+            import("__wbindgen_throw"),
+        ];
+        let functions = host_functions(BINDINGS, &imports);
+        assert_eq!(functions, vec!["add", "alert", "render"]);
+        assert_eq!(unresolved(&functions), vec!["add", "render"]);
+    }
+
+    #[test]
+    fn stub_missing_functions() {
+        let stubs = stubs("app", &["add".to_string()]);
+        assert!(stubs.contains("if (typeof window.add !== 'function') {"));
+        assert!(stubs.contains("window.add = stub('add');"));
+        assert_eq!(stubs_file_name("app"), "app_imports.js");
+    }
+}
@@ -1,10 +1,53 @@
+extern crate addr2line;
+extern crate brotli;
 extern crate clap;
+extern crate flate2;
+extern crate futures;
+extern crate gimli;
+extern crate glob;
+#[macro_use]
+extern crate hyper;
+extern crate openssl;
+extern crate rustc_demangle;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
+extern crate tokio_core;
+extern crate tokio_io;
+extern crate wasmparser;
 
 mod bindgen;
 pub mod build;
 mod cargo;
+mod clean;
+mod compress;
+mod console;
+mod diagnostics;
+mod headers;
+mod html;
+mod imports;
+mod listing;
+mod logging;
+mod proxy;
+mod range;
+pub mod run;
+mod scaffold;
+mod size;
+mod strip;
+mod symbolicate;
+#[cfg(test)]
+mod test_wasm;
+mod tls;
 mod util;
+mod validate;
+
+pub use clean::{clean, Error as CleanError, Options as CleanOptions, Removal};
+pub use run::{serve, serve_build_error};
+pub use scaffold::{
+    init as init_project, new as new_project, Error as ScaffoldError, Options as ScaffoldOptions,
+    Template,
+};
+pub use size::{analyze_file as analyze_size, table as size_table};
+pub use symbolicate::{Frame, Symbols};
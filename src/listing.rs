@@ -0,0 +1,95 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use hyper::header::HttpDate;
+
+use util::escape_html;
+
+struct Entry {
+    name: String,
+    is_dir: bool,
+    len: u64,
+    modified: Option<HttpDate>,
+}
+
+fn escape_href(s: &str) -> String {
+    let mut escaped = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                escaped.push(b as char)
+            }
+            _ => escaped.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    escaped
+}
+
+fn read_entries(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        entries.push(Entry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            is_dir: metadata.is_dir(),
+            len: metadata.len(),
+            modified: metadata
+                .modified()
+                .ok()
+                .filter(|modified| *modified > UNIX_EPOCH)
+                .map(HttpDate::from),
+        });
+    }
+    // Directories first, then by name
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+/// Renders an html listing of a directory served at `request_path`
+pub fn render(dir: &Path, request_path: &str) -> io::Result<String> {
+    let title = format!("Index of {}", escape_html(request_path));
+    let mut rows = String::new();
+    if request_path != "/" {
+        rows.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in read_entries(dir)? {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        let size = if entry.is_dir {
+            String::new()
+        } else {
+            entry.len.to_string()
+        };
+        let modified = entry
+            .modified
+            .map(|modified| modified.to_string())
+            .unwrap_or_default();
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{href}{suffix}\">{name}{suffix}</a></td><td>{size}</td><td>{modified}</td></tr>\n",
+            href = escape_href(&entry.name),
+            name = escape_html(&entry.name),
+            suffix = suffix,
+            size = size,
+            modified = modified
+        ));
+    }
+    Ok(format!(
+        r#"<html>
+    <head>
+        <meta content="text/html;charset=utf-8" http-equiv="Content-Type"/>
+        <title>{title}</title>
+    </head>
+    <body>
+        <h1>{title}</h1>
+        <table>
+            <tr><th align="left">Name</th><th align="right">Size</th><th align="left">Modified</th></tr>
+{rows}        </table>
+    </body>
+</html>
+"#,
+        title = title,
+        rows = rows
+    ))
+}
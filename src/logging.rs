@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use hyper::header::HttpDate;
+
+use util::format_size;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum LogFormat {
+    /// One short line per request, colored when written to a terminal
+    #[default]
+    Compact,
+    /// Common Log Format as used by Apache and nginx
+    Common,
+    /// Common Log Format followed by the referer and user agent
+    Combined,
+    /// One JSON object per line
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s {
+            "compact" => Ok(LogFormat::Compact),
+            "common" => Ok(LogFormat::Common),
+            "combined" => Ok(LogFormat::Combined),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format `{}`", s)),
+        }
+    }
+}
+
+/// A served request
+pub struct RequestLog {
+    pub time: SystemTime,
+    pub remote_addr: SocketAddr,
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub status: u16,
+    /// Bytes of the response body sent to the client
+    pub bytes: u64,
+    pub latency: Duration,
+}
+
+/// Writes request logs to stdout or a file
+pub struct Logger {
+    format: LogFormat,
+    file: Option<RefCell<File>>,
+    color: bool,
+}
+
+impl Logger {
+    pub fn stdout(format: LogFormat) -> Logger {
+        Logger {
+            format,
+            file: None,
+            color: io::stdout().is_terminal(),
+        }
+    }
+
+    /// Logs to a file, appending to it if it exists
+    pub fn file(format: LogFormat, path: &Path) -> io::Result<Logger> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Logger {
+            format,
+            file: Some(RefCell::new(file)),
+            color: false,
+        })
+    }
+
+    pub fn log(&self, request: &RequestLog) {
+        let line = match self.format {
+            LogFormat::Compact => self.compact(request),
+            LogFormat::Common => common(request),
+            LogFormat::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                common(request),
+                request.referer.as_deref().unwrap_or("-"),
+                request.user_agent.as_deref().unwrap_or("-")
+            ),
+            LogFormat::Json => json(request),
+        };
+        match self.file {
+            Some(ref file) => {
+                if let Err(e) = writeln!(file.borrow_mut(), "{}", line) {
+                    println!("wasm-bin: Failed to write request log: {}", e);
+                }
+            }
+            None => println!("{}", line),
+        }
+    }
+
+    fn compact(&self, request: &RequestLog) -> String {
+        let status = if self.color {
+            let color = match request.status {
+                200..=299 => 32,
+                300..=399 => 36,
+                400..=499 => 33,
+                _ => 31,
+            };
+            format!("\x1b[{}m{}\x1b[0m", color, request.status)
+        } else {
+            request.status.to_string()
+        };
+        format!(
+            "wasm-bin: {} {} {} {} {:.1}ms",
+            request.method,
+            request.uri,
+            status,
+            format_size(request.bytes),
+            millis(request.latency)
+        )
+    }
+}
+
+fn common(request: &RequestLog) -> String {
+    // Like Apache's `%b`, an empty body is logged as `-`
+    let bytes = match request.bytes {
+        0 => "-".to_string(),
+        bytes => bytes.to_string(),
+    };
+    format!(
+        "{} - - [{}] \"{} {} {}\" {} {}",
+        request.remote_addr.ip(),
+        common_time(request.time),
+        request.method,
+        request.uri,
+        request.version,
+        request.status,
+        bytes
+    )
+}
+
+fn json(request: &RequestLog) -> String {
+    json!({
+        "time": iso_time(request.time),
+        "remote_addr": request.remote_addr.to_string(),
+        "method": request.method,
+        "uri": request.uri,
+        "version": request.version,
+        "status": request.status,
+        "bytes": request.bytes,
+        "latency_ms": millis(request.latency),
+    })
+    .to_string()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
+
+// Day, month name, year and time of day in UTC, taken from the http date of a time, e.g.
+// `Sun, 06 Nov 1994 08:49:37 GMT`
+fn utc(time: SystemTime) -> [String; 4] {
+    let date = HttpDate::from(time).to_string();
+    let mut fields = date.split_whitespace().skip(1).map(String::from);
+    let mut next = || fields.next().unwrap_or_default();
+    [next(), next(), next(), next()]
+}
+
+fn common_time(time: SystemTime) -> String {
+    let [day, month, year, time] = utc(time);
+    format!("{}/{}/{}:{} +0000", day, month, year, time)
+}
+
+fn iso_time(time: SystemTime) -> String {
+    let [day, month, year, time] = utc(time);
+    let month = MONTHS.iter().position(|name| *name == month).unwrap_or(0) + 1;
+    format!("{}-{:02}-{}T{}Z", year, month, day, time)
+}
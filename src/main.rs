@@ -1,16 +1,21 @@
 extern crate clap;
-extern crate futures;
+extern crate ctrlc;
 #[macro_use]
-extern crate hyper;
-extern crate serde;
 extern crate serde_json;
 extern crate wasm_bin;
 
-mod run;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::process;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
 
 use wasm_bin::build;
+use wasm_bin::run;
+use wasm_bin::Symbols;
 
 fn shared_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
     vec![
@@ -37,6 +42,12 @@ fn shared_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
         Arg::with_name("release")
             .long("release")
             .help("Build artifacts in release mode, with optimizations"),
+        Arg::with_name("profile")
+            .long("profile")
+            .value_name("PROFILE-NAME")
+            .help("Build artifacts with the specified profile")
+            .takes_value(true)
+            .conflicts_with("release"),
         Arg::with_name("example")
             .long("example")
             .value_name("NAME")
@@ -77,6 +88,57 @@ fn shared_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
             .value_name("FLAG")
             .help("Unstable (nightly-only) flags to Cargo")
             .takes_value(true),
+        Arg::with_name("yes")
+            .short("y")
+            .long("yes")
+            .help("Install wasm-bindgen without asking when it is missing"),
+        Arg::with_name("no-install")
+            .long("no-install")
+            .help("Never install wasm-bindgen, fail if it is missing instead")
+            .conflicts_with("yes"),
+        Arg::with_name("bindgen-path")
+            .long("bindgen-path")
+            .value_name("PATH")
+            .help("Install wasm-bindgen from a local checkout of the wasm-bindgen-cli crate")
+            .takes_value(true),
+        Arg::with_name("bindgen-vendor")
+            .long("bindgen-vendor")
+            .value_name("DIR")
+            .help("Install wasm-bindgen offline from crates vendored with `cargo vendor`")
+            .takes_value(true)
+            .conflicts_with("bindgen-path"),
+        Arg::with_name("entry")
+            .long("entry")
+            .value_name("NAME")
+            .help("Exported function called by the html loader, defaults to main")
+            .takes_value(true),
+        Arg::with_name("no-entry")
+            .long("no-entry")
+            .help("Don't call any exported function after loading")
+            .conflicts_with("entry"),
+        Arg::with_name("entry-query")
+            .long("entry-query")
+            .help("Pass the page's query string to the entry function"),
+        Arg::with_name("entry-json")
+            .long("entry-json")
+            .value_name("JSON")
+            .help("Pass a JSON value, as a string, to the entry function")
+            .takes_value(true)
+            .conflicts_with("entry-query")
+            .validator(|value| {
+                serde_json::from_str::<serde_json::Value>(&value)
+                    .map(|_| ())
+                    .map_err(|e| format!("invalid JSON: {}", e))
+            }),
+        Arg::with_name("import-stubs")
+            .long("import-stubs")
+            .help("Write placeholders for the functions the bindings import from the page"),
+        Arg::with_name("strip-debug")
+            .long("strip-debug")
+            .help("Remove custom sections, such as names and debug info, from the wasm modules"),
+        Arg::with_name("split-debug")
+            .long("split-debug")
+            .help("Like --strip-debug, but keep the unstripped modules as <target>.debug.wasm"),
     ]
 }
 
@@ -94,9 +156,332 @@ fn build_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
         Arg::with_name("all-targets")
             .long("all-targets")
             .help("Build all targets (lib and bin targets by default)"),
+        Arg::with_name("precompress")
+            .long("precompress")
+            .help("Write brotli (.br) and gzip (.gz) compressed copies of the output files"),
     ]
 }
 
+fn run_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("https").long("https").help(
+            "Serve over https, with a self-signed certificate unless --cert and --key are given",
+        ),
+        Arg::with_name("cert")
+            .long("cert")
+            .value_name("PATH")
+            .help("PEM certificate (chain) to serve https with")
+            .takes_value(true)
+            .requires_all(&["https", "key"]),
+        Arg::with_name("key")
+            .long("key")
+            .value_name("PATH")
+            .help("PEM private key of the https certificate")
+            .takes_value(true)
+            .requires_all(&["https", "cert"]),
+        Arg::with_name("cross-origin-isolated")
+            .long("cross-origin-isolated")
+            .help("Send the COOP and COEP headers required for SharedArrayBuffer and wasm threads"),
+        Arg::with_name("header")
+            .long("header")
+            .value_name("[GLOB=]NAME: VALUE")
+            .help("Add a header to responses for paths matching GLOB, or all paths")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .validator(|value| value.parse::<run::HeaderRule>().map(|_| ())),
+        Arg::with_name("proxy")
+            .long("proxy")
+            .value_name("GLOB=URL")
+            .help("Forward requests for paths matching GLOB to an http server, e.g. /api/*=http://127.0.0.1:3000")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .validator(|value| value.parse::<run::ProxyRule>().map(|_| ())),
+        Arg::with_name("spa")
+            .long("spa")
+            .help("Serve the index page for unknown paths without a file extension that accept html"),
+        Arg::with_name("list-dirs")
+            .long("list-dirs")
+            .help("Show a listing of served directories that have no index.html"),
+        Arg::with_name("no-console")
+            .long("no-console")
+            .help("Don't print the browser console output and uncaught errors of the page"),
+        Arg::with_name("log-format")
+            .long("log-format")
+            .value_name("FORMAT")
+            .help("Format of request logs")
+            .takes_value(true)
+            .possible_values(&["compact", "common", "combined", "json"])
+            .default_value("compact"),
+        Arg::with_name("log-file")
+            .long("log-file")
+            .value_name("PATH")
+            .help("Append request logs to a file instead of printing them")
+            .takes_value(true),
+    ]
+}
+
+fn symbolicate_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("target")
+            .value_name("TARGET")
+            .help("Name of the binary or example the trace is from")
+            .required(true),
+        Arg::with_name("trace")
+            .value_name("FILE")
+            .help("File containing the stack trace, read from stdin if not given"),
+        Arg::with_name("wasm")
+            .long("wasm")
+            .value_name("PATH")
+            .help("Wasm module to read symbols from, defaults to the module built for TARGET")
+            .takes_value(true),
+        Arg::with_name("release")
+            .long("release")
+            .help("Read symbols from the module built in release mode"),
+        Arg::with_name("profile")
+            .long("profile")
+            .value_name("PROFILE-NAME")
+            .help("Read symbols from the module built with the specified profile")
+            .takes_value(true)
+            .conflicts_with("release"),
+    ]
+}
+
+fn symbolicate(matches: &ArgMatches) {
+    let target = matches.value_of("target").unwrap();
+    let wasm = matches
+        .value_of("wasm")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| build::wasm_path(&build::profile_dir(&build_options(matches)), target));
+    let symbols = Symbols::load(&wasm).unwrap();
+    let trace = match matches.value_of("trace") {
+        Some(path) => fs::read_to_string(path).unwrap(),
+        None => {
+            let mut trace = String::new();
+            io::stdin().read_to_string(&mut trace).unwrap();
+            trace
+        }
+    };
+    println!("{}", symbols.symbolicate(&trace));
+}
+
+fn scaffold_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("name")
+            .long("name")
+            .value_name("NAME")
+            .help("Package name, defaults to the directory name")
+            .takes_value(true),
+        Arg::with_name("lib")
+            .long("lib")
+            .help("Use a cdylib library template instead of a binary"),
+        Arg::with_name("html")
+            .long("html")
+            .help("Also write an html template for the target to customize"),
+        Arg::with_name("static")
+            .long("static")
+            .help("Also create a static folder, whose files are copied next to the outputs"),
+    ]
+}
+
+fn scaffold(matches: &ArgMatches, new: bool) {
+    let options = wasm_bin::ScaffoldOptions {
+        name: matches.value_of("name").map(String::from),
+        template: if matches.is_present("lib") {
+            wasm_bin::Template::Lib
+        } else {
+            wasm_bin::Template::Bin
+        },
+        html: matches.is_present("html"),
+        static_dir: matches.is_present("static"),
+    };
+    let dir = PathBuf::from(matches.value_of("path").unwrap_or("."));
+    let files = if new {
+        wasm_bin::new_project(&dir, &options).unwrap()
+    } else {
+        wasm_bin::init_project(&dir, &options).unwrap()
+    };
+    for file in files {
+        println!("wasm-bin: Created {}", file.display());
+    }
+}
+
+fn clean_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("package")
+            .long("package")
+            .value_name("SPEC")
+            .help("Only clean the outputs of the package's targets")
+            .takes_value(true),
+        Arg::with_name("bin")
+            .long("bin")
+            .value_name("NAME")
+            .help("Only clean the outputs of the specified binary")
+            .takes_value(true),
+        Arg::with_name("example")
+            .long("example")
+            .value_name("NAME")
+            .help("Only clean the outputs of the specified example")
+            .takes_value(true),
+        Arg::with_name("release")
+            .long("release")
+            .help("Only clean the outputs built in release mode"),
+        Arg::with_name("profile")
+            .long("profile")
+            .value_name("PROFILE-NAME")
+            .help("Only clean the outputs built with the specified profile")
+            .takes_value(true)
+            .conflicts_with("release"),
+        Arg::with_name("manifest-path")
+            .long("manifest-path")
+            .value_name("PATH")
+            .help("Path to the manifest of the package to clean")
+            .takes_value(true),
+        Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("List what would be removed without removing anything"),
+    ]
+}
+
+fn clean(matches: &ArgMatches) {
+    let profile_dir = if matches.is_present("release") || matches.is_present("profile") {
+        Some(build::profile_dir(&build_options(matches)))
+    } else {
+        None
+    };
+    let options = wasm_bin::CleanOptions {
+        package: matches.value_of("package").map(String::from),
+        bin: matches.value_of("bin").map(String::from),
+        example: matches.value_of("example").map(String::from),
+        manifest_path: matches.value_of("manifest-path").map(String::from),
+        profile_dir,
+        dry_run: matches.is_present("dry-run"),
+    };
+    wasm_bin::clean(&options).unwrap();
+}
+
+fn size_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("json")
+            .long("json")
+            .help("Print the report as JSON"),
+        Arg::with_name("top")
+            .short("n")
+            .long("top")
+            .value_name("N")
+            .help("Number of functions and crates to list, defaults to 20")
+            .takes_value(true)
+            .validator(|value| {
+                value
+                    .parse::<usize>()
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            }),
+    ]
+}
+
+fn size(matches: &ArgMatches) {
+    let targets = build::build(&build_options(matches)).unwrap();
+    let limit = matches
+        .value_of("top")
+        .map(|top| top.parse().unwrap())
+        .unwrap_or(20);
+    let mut reports = Vec::new();
+    for target in targets {
+        let report = wasm_bin::analyze_size(&target.wasm_path).unwrap();
+        if matches.is_present("json") {
+            reports.push(json!({
+                "target": target.name,
+                "path": target.wasm_path,
+                "report": report,
+            }));
+        } else {
+            println!(
+                "\nwasm-bin: Size of '{}' ({})\n",
+                target.name,
+                target.wasm_path.display()
+            );
+            print!("{}", wasm_bin::size_table(&report, limit));
+        }
+    }
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    }
+}
+
+fn run_options(matches: &ArgMatches, build_options: &build::Options) -> run::Options {
+    let tls_identity = match (matches.value_of("cert"), matches.value_of("key")) {
+        (Some(cert), Some(key)) => Some(run::Identity {
+            cert: PathBuf::from(cert),
+            key: PathBuf::from(key),
+        }),
+        _ => None,
+    };
+    run::Options {
+        addr: None,
+        entry: build_options.entry.clone(),
+        https: matches.is_present("https"),
+        tls_identity,
+        cert_dir: build::output_root(build_options.manifest_path.as_deref())
+            .ok()
+            .map(|root| root.join("certs")),
+        cross_origin_isolated: matches.is_present("cross-origin-isolated"),
+        headers: matches
+            .values_of("header")
+            .map(|values| values.map(|value| value.parse().unwrap()).collect())
+            .unwrap_or_default(),
+        proxy_rules: matches
+            .values_of("proxy")
+            .map(|values| values.map(|value| value.parse().unwrap()).collect())
+            .unwrap_or_default(),
+        spa: matches.is_present("spa"),
+        list_dirs: matches.is_present("list-dirs"),
+        forward_console: !matches.is_present("no-console"),
+        log_format: matches.value_of("log-format").unwrap().parse().unwrap(),
+        log_file: matches.value_of("log-file").map(PathBuf::from),
+    }
+}
+
+fn entry(matches: &ArgMatches) -> build::Entry {
+    let function = if matches.is_present("no-entry") {
+        None
+    } else {
+        Some(matches.value_of("entry").unwrap_or("main").to_string())
+    };
+    let args = if matches.is_present("entry-query") {
+        build::EntryArgs::Query
+    } else if let Some(json) = matches.value_of("entry-json") {
+        build::EntryArgs::Json(serde_json::from_str(json).unwrap())
+    } else {
+        build::EntryArgs::None
+    };
+    build::Entry { function, args }
+}
+
+fn install_policy(matches: &ArgMatches) -> build::InstallPolicy {
+    if matches.is_present("yes") {
+        build::InstallPolicy::Always
+    } else if matches.is_present("no-install") {
+        build::InstallPolicy::Never
+    } else {
+        match env::var(build::INSTALL_ENV) {
+            Err(env::VarError::NotPresent) => build::InstallPolicy::default(),
+            policy => policy
+                .map_err(|e| e.to_string())
+                .and_then(|policy| policy.parse())
+                .unwrap_or_else(|e| {
+                    println!(
+                        "wasm-bin: Invalid {}: {}, expected always, never or prompt",
+                        build::INSTALL_ENV,
+                        e
+                    );
+                    process::exit(1)
+                }),
+        }
+    }
+}
+
 fn build_options(matches: &ArgMatches) -> build::Options {
     let mut build_options = build::Options::default();
     if let Some(bin) = matches.value_of("bin") {
@@ -116,6 +501,7 @@ fn build_options(matches: &ArgMatches) -> build::Options {
         example: matches.value_of("example").map(String::from),
         all_targets: matches.is_present("all-targets"),
         release: matches.is_present("release"),
+        profile: matches.value_of("profile").map(String::from),
         features: matches.value_of("features").map(String::from),
         all_features: matches.is_present("all-features"),
         no_default_features: matches.is_present("no-default-features"),
@@ -125,6 +511,19 @@ fn build_options(matches: &ArgMatches) -> build::Options {
         frozen: matches.is_present("frozen"),
         locked: matches.is_present("locked"),
         cargo_flags: matches.value_of("Z").map(String::from),
+        install: install_policy(matches),
+        bindgen_source: if let Some(path) = matches.value_of("bindgen-path") {
+            build::InstallSource::Path(PathBuf::from(path))
+        } else if let Some(dir) = matches.value_of("bindgen-vendor") {
+            build::InstallSource::Vendored(PathBuf::from(dir))
+        } else {
+            build::InstallSource::CratesIo
+        },
+        entry: entry(matches),
+        precompress: matches.is_present("precompress"),
+        import_stubs: matches.is_present("import-stubs"),
+        strip_debug: matches.is_present("strip-debug"),
+        split_debug: matches.is_present("split-debug"),
     }
 }
 
@@ -135,7 +534,48 @@ fn main() {
                 .args(&shared_args())
                 .args(&build_args()),
         )
-        .subcommand(SubCommand::with_name("run").args(&shared_args()))
+        .subcommand(
+            SubCommand::with_name("run")
+                .args(&shared_args())
+                .args(&run_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("new")
+                .about("Creates a new wasm app project")
+                .arg(
+                    Arg::with_name("path")
+                        .value_name("PATH")
+                        .help("Directory to create")
+                        .required(true),
+                )
+                .args(&scaffold_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("init")
+                .about("Creates a new wasm app project in an existing directory")
+                .arg(
+                    Arg::with_name("path")
+                        .value_name("PATH")
+                        .help("Directory of the project, defaults to the current directory"),
+                )
+                .args(&scaffold_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("clean")
+                .about("Removes the files generated by wasm-bin")
+                .args(&clean_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("size")
+                .about("Reports what takes up space in the built wasm modules")
+                .args(&shared_args())
+                .args(&size_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("symbolicate")
+                .about("Resolves the wasm-function[N]:0xOFFSET frames of a stack trace")
+                .args(&symbolicate_args()),
+        )
         .get_matches();
 
     if let Some(matches) = app.subcommand_matches("build") {
@@ -143,14 +583,40 @@ fn main() {
         build::build(&options).unwrap();
     } else if let Some(matches) = app.subcommand_matches("run") {
         let options = build_options(matches);
-        let targets = build::build(&options).unwrap();
-        for target in targets {
-            match target.ty {
-                build::PackageType::Binary => {
-                    run::serve(target.name, target.path);
+        let run_options = run_options(matches, &options);
+        let server = match build::build(&options) {
+            Ok(targets) => {
+                let targets: Vec<_> = targets
+                    .into_iter()
+                    .filter(|target| matches!(target.ty, build::PackageType::Binary))
+                    .collect();
+                if targets.is_empty() {
+                    println!("wasm-bin: No binary targets to serve");
+                    return;
                 }
-                _ => {}
+                wasm_bin::serve(&targets, &run_options).unwrap()
             }
-        }
+            Err(e) => {
+                println!("wasm-bin: Build failed, serving the errors instead");
+                wasm_bin::serve_build_error(&e, &run_options).unwrap()
+            }
+        };
+        let shutdown = server.shutdown_handle();
+        ctrlc::set_handler(move || {
+            println!("wasm-bin: Shutting down");
+            shutdown.shutdown();
+        })
+        .unwrap();
+        server.wait().unwrap();
+    } else if let Some(matches) = app.subcommand_matches("new") {
+        scaffold(matches, true);
+    } else if let Some(matches) = app.subcommand_matches("init") {
+        scaffold(matches, false);
+    } else if let Some(matches) = app.subcommand_matches("clean") {
+        clean(matches);
+    } else if let Some(matches) = app.subcommand_matches("size") {
+        size(matches);
+    } else if let Some(matches) = app.subcommand_matches("symbolicate") {
+        symbolicate(matches);
     }
 }
@@ -0,0 +1,342 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::prelude::*;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use futures::sync::oneshot;
+use futures::{future, Async, Future, Poll};
+use glob::Pattern;
+use hyper;
+use hyper::client::{conn, Client, HttpConnector, Service};
+use hyper::header::{ContentLength, Headers, Host};
+use hyper::{Request, Response, StatusCode, Uri};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+use tokio_io::io::copy;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+// Headers that only apply to a single connection and must not be forwarded
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// Forwards requests whose path matches a glob pattern to an upstream http server.
+///
+/// Parsed from `GLOB=URL`, e.g. `/api/*=http://127.0.0.1:3000`. The request path and query are
+/// appended to the path of the upstream URL.
+#[derive(Clone, Debug)]
+pub struct ProxyRule {
+    pub pattern: Pattern,
+    pub upstream: Uri,
+}
+
+impl FromStr for ProxyRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ProxyRule, String> {
+        let eq = s
+            .find('=')
+            .ok_or_else(|| format!("expected `GLOB=URL`, got `{}`", s))?;
+        let (pattern, upstream) = (s[..eq].trim(), s[eq + 1..].trim());
+        let upstream: Uri = upstream
+            .parse()
+            .map_err(|e| format!("invalid upstream URL `{}`: {}", upstream, e))?;
+        if upstream.scheme() != Some("http") || upstream.host().is_none() {
+            return Err(format!(
+                "upstream URL `{}` must be an absolute http URL",
+                upstream
+            ));
+        }
+        Ok(ProxyRule {
+            pattern: Pattern::new(pattern)
+                .map_err(|e| format!("invalid glob `{}`: {}", pattern, e))?,
+            upstream,
+        })
+    }
+}
+
+impl ProxyRule {
+    // The request target at the upstream, the path and query of a request prefixed with the
+    // path of the upstream URL
+    fn upstream_target(&self, req: &Request) -> String {
+        let path = format!(
+            "{}{}",
+            self.upstream.path().trim_end_matches('/'),
+            req.path()
+        );
+        match req.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        }
+    }
+
+    fn upstream_authority(&self) -> String {
+        let host = self.upstream.host().unwrap_or("localhost");
+        match self.upstream.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => format!("{}:80", host),
+        }
+    }
+}
+
+/// The first rule matching a request path
+pub fn find<'a>(rules: &'a [ProxyRule], path: &str) -> Option<&'a ProxyRule> {
+    rules.iter().find(|rule| rule.pattern.matches(path))
+}
+
+// The message is left to the response and its request log line
+fn bad_gateway(message: String) -> Response {
+    Response::new()
+        .with_status(StatusCode::BadGateway)
+        .with_header(ContentLength(message.len() as u64))
+        .with_body(message)
+}
+
+// Names of the headers the `Connection` headers list, as lowercase
+fn connection_options(headers: &Headers) -> Vec<String> {
+    let raw = match headers.get_raw("Connection") {
+        Some(raw) => raw,
+        None => return Vec::new(),
+    };
+    raw.iter()
+        .flat_map(|line| {
+            String::from_utf8_lossy(line)
+                .split(',')
+                .map(|option| option.trim().to_lowercase())
+                .filter(|option| !option.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// Removes the headers that only apply to a single connection, including those listed in the
+// `Connection` header (RFC 7230, section 6.1)
+fn remove_hop_by_hop(headers: &mut Headers) {
+    for name in connection_options(headers) {
+        headers.remove_raw(&name);
+    }
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove_raw(name);
+    }
+}
+
+/// Whether a request asks to switch the connection to another protocol, e.g. a websocket
+pub fn is_upgrade(req: &Request) -> bool {
+    req.headers().get_raw("Upgrade").is_some()
+        && connection_options(req.headers())
+            .iter()
+            .any(|option| option == "upgrade")
+}
+
+// A request for the upstream with the method, headers and body of a client request
+fn upstream_request(req: Request, uri: Uri, https: bool) -> Request {
+    let (method, _, _, mut headers, body) = req.deconstruct();
+    let original_host = headers.get::<Host>().map(|host| host.to_string());
+    headers.remove::<Host>();
+    remove_hop_by_hop(&mut headers);
+    if let Some(host) = original_host {
+        headers.set_raw("X-Forwarded-Host", host);
+    }
+    headers.set_raw("X-Forwarded-Proto", if https { "https" } else { "http" });
+
+    let mut upstream_req = Request::new(method, uri);
+    *upstream_req.headers_mut() = headers;
+    upstream_req.set_body(body);
+    upstream_req
+}
+
+/// Forwards a request to the upstream of a rule and streams the response back
+pub fn forward(
+    client: &Client<HttpConnector>,
+    rule: &ProxyRule,
+    req: Request,
+    https: bool,
+) -> Box<dyn Future<Item = Response, Error = hyper::Error>> {
+    let uri = format!(
+        "http://{}{}",
+        rule.upstream_authority(),
+        rule.upstream_target(&req)
+    );
+    let uri: Uri = match uri.parse() {
+        Ok(uri) => uri,
+        Err(e) => return Box::new(future::ok(bad_gateway(format!("{}: {}", uri, e)))),
+    };
+    let upstream_req = upstream_request(req, uri.clone(), https);
+    Box::new(client.request(upstream_req).then(move |result| {
+        Ok(match result {
+            Ok(mut response) => {
+                remove_hop_by_hop(response.headers_mut());
+                response
+            }
+            Err(e) => bad_gateway(format!("{}: {}", uri, e)),
+        })
+    }))
+}
+
+/// Upstream connection of a request that switched protocols, along with the bytes the upstream
+/// sent after its response
+pub struct Upgraded {
+    stream: TcpStream,
+    read_buf: Vec<u8>,
+}
+
+/// Where the service leaves the upstream connection of an upgrade, to be tunneled to once the
+/// response is sent and the client connection is handed back
+pub type UpgradeSlot = Rc<RefCell<Option<Upgraded>>>;
+
+/// Forwards a request to upgrade the connection to the upstream of a rule, on a connection of
+/// its own. When the upstream switches protocols, its connection is left in `slot`.
+pub fn upgrade(
+    connector: &HttpConnector,
+    handle: &Handle,
+    rule: &ProxyRule,
+    req: Request,
+    https: bool,
+    slot: UpgradeSlot,
+) -> Box<dyn Future<Item = Response, Error = hyper::Error>> {
+    let authority = rule.upstream_authority();
+    let target = rule.upstream_target(&req);
+    let (uri, target): (Uri, Uri) = match (format!("http://{}", authority).parse(), target.parse())
+    {
+        (Ok(uri), Ok(target)) => (uri, target),
+        _ => {
+            let message = format!("invalid upstream URL http://{}{}", authority, target);
+            return Box::new(future::ok(bad_gateway(message)));
+        }
+    };
+    let protocol = req.headers().get_raw("Upgrade").cloned().unwrap();
+    // The connection only sends this request, so the target is sent in origin form
+    let mut upstream_req = upstream_request(req, target, https);
+    upstream_req
+        .headers_mut()
+        .set_raw("Host", authority.clone());
+    upstream_req.headers_mut().set_raw("Upgrade", protocol);
+    upstream_req.headers_mut().set_raw("Connection", "Upgrade");
+
+    let handle = handle.clone();
+    let response = connector
+        .call(uri)
+        .map_err(hyper::Error::from)
+        .and_then(conn::handshake)
+        .and_then(move |(mut sender, connection)| {
+            // Drive the connection until it is done, which is after the response for an
+            // upgrade, and hand back its stream
+            let (parts_sender, parts) = oneshot::channel();
+            let mut connection = Some(connection);
+            let done = future::poll_fn(move || {
+                match connection.as_mut().unwrap().poll_without_shutdown()? {
+                    Async::Ready(()) => Ok(Async::Ready(connection.take().unwrap().into_parts())),
+                    Async::NotReady => Ok(Async::NotReady),
+                }
+            });
+            handle.spawn(done.then(|result: Result<_, hyper::Error>| {
+                let _ = parts_sender.send(result);
+                Ok(())
+            }));
+
+            sender.send_request(upstream_req).and_then(move |response| {
+                if response.status() != StatusCode::SwitchingProtocols {
+                    return future::Either::A(future::ok(response));
+                }
+                future::Either::B(parts.then(move |parts| match parts {
+                    Ok(Ok(parts)) => {
+                        *slot.borrow_mut() = Some(Upgraded {
+                            stream: parts.io,
+                            read_buf: parts.read_buf.to_vec(),
+                        });
+                        Ok(response)
+                    }
+                    Ok(Err(e)) => Err(e),
+                    Err(_) => Err(hyper::Error::Closed),
+                }))
+            })
+        });
+    Box::new(response.then(move |result| {
+        Ok(match result {
+            Ok(mut response) => {
+                let upgrade = response.headers().get_raw("Upgrade").cloned();
+                remove_hop_by_hop(response.headers_mut());
+                if let (StatusCode::SwitchingProtocols, Some(protocol)) =
+                    (response.status(), upgrade)
+                {
+                    response.headers_mut().set_raw("Upgrade", protocol);
+                    response.headers_mut().set_raw("Connection", "Upgrade");
+                }
+                response
+            }
+            Err(e) => bad_gateway(format!("http://{}: {}", authority, e)),
+        })
+    }))
+}
+
+/// Relays bytes in both directions between a client connection and the upstream connection of
+/// an upgrade, until either side closes
+pub fn tunnel<S: AsyncRead + AsyncWrite + 'static>(
+    client: S,
+    upstream: Upgraded,
+) -> Box<dyn Future<Item = (), Error = io::Error>> {
+    let upstream = Rewind::new(upstream.read_buf, upstream.stream);
+    let (client_read, client_write) = client.split();
+    let (upstream_read, upstream_write) = upstream.split();
+    Box::new(
+        copy(client_read, upstream_write)
+            .map(|_| ())
+            .select(copy(upstream_read, client_write).map(|_| ()))
+            .map(|_| ())
+            .map_err(|(e, _)| e),
+    )
+}
+
+/// A stream that yields already read bytes before reading from the inner stream
+pub struct Rewind<S> {
+    prefix: Vec<u8>,
+    position: usize,
+    inner: S,
+}
+
+impl<S> Rewind<S> {
+    pub fn new(prefix: Vec<u8>, inner: S) -> Rewind<S> {
+        Rewind {
+            prefix,
+            position: 0,
+            inner,
+        }
+    }
+}
+
+impl<S: Read> Read for Rewind<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position < self.prefix.len() {
+            let read = (&self.prefix[self.position..]).read(buf)?;
+            self.position += read;
+            return Ok(read);
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Write> Write for Rewind<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Rewind<S> {}
+
+impl<S: AsyncWrite> AsyncWrite for Rewind<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
@@ -0,0 +1,167 @@
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::header::{EntityTag, IfRange, Range};
+use hyper::Request;
+
+/// Most parts sent in a `multipart/byteranges` response, more ranges get the full content
+pub const MAX_RANGES: usize = 16;
+
+/// Byte ranges requested by the `Range` header of a request
+pub enum Ranges {
+    /// No usable `Range` header, the full content is sent
+    Full,
+    /// Inclusive byte ranges to send with `206 Partial Content`
+    Partial(Vec<(u64, u64)>),
+    /// None of the requested ranges overlap the content
+    Unsatisfiable,
+}
+
+/// Evaluates the `Range` and `If-Range` headers against content of `len` bytes
+pub fn requested(req: &Request, etag: &EntityTag, modified: SystemTime, len: u64) -> Ranges {
+    let specs = match req.headers().get::<Range>() {
+        Some(Range::Bytes(specs)) => specs,
+        _ => return Ranges::Full,
+    };
+    // A stale If-Range validator means the client wants the whole, changed content
+    if let Some(if_range) = req.headers().get::<IfRange>() {
+        let unchanged = match *if_range {
+            IfRange::EntityTag(ref tag) => tag.strong_eq(etag),
+            IfRange::Date(date) => seconds(modified) == seconds(date.into()),
+        };
+        if !unchanged {
+            return Ranges::Full;
+        }
+    }
+
+    let mut ranges: Vec<(u64, u64)> = specs
+        .iter()
+        .filter_map(|spec| spec.to_satisfiable_range(len))
+        .collect();
+    if ranges.is_empty() {
+        return Ranges::Unsatisfiable;
+    }
+    let ranges = merge(&mut ranges);
+    if ranges.len() > MAX_RANGES {
+        Ranges::Full
+    } else {
+        Ranges::Partial(ranges)
+    }
+}
+
+// Coalesces overlapping and adjacent ranges, so no byte is sent twice
+fn merge(ranges: &mut [(u64, u64)]) -> Vec<(u64, u64)> {
+    ranges.sort();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for &(start, end) in ranges.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = cmp::max(last.1, end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Boundary separating the parts of a `multipart/byteranges` body
+pub fn boundary(etag: &EntityTag) -> String {
+    let mut hasher = DefaultHasher::new();
+    etag.tag().hash(&mut hasher);
+    SystemTime::now().hash(&mut hasher);
+    format!("wasm-bin-{:016x}", hasher.finish())
+}
+
+enum Segment {
+    Bytes(Vec<u8>),
+    // Inclusive byte range of the content
+    Range(u64, u64),
+}
+
+/// Reads a `multipart/byteranges` body, copying each range from the content as it goes
+pub struct Multipart<R> {
+    content: R,
+    segments: VecDeque<Segment>,
+    length: u64,
+}
+
+impl<R: Read + Seek> Multipart<R> {
+    pub fn new(
+        content: R,
+        boundary: &str,
+        content_type: Option<&str>,
+        len: u64,
+        ranges: &[(u64, u64)],
+    ) -> Multipart<R> {
+        let mut segments = VecDeque::new();
+        for &(start, end) in ranges {
+            let mut header = format!("--{}\r\n", boundary);
+            if let Some(content_type) = content_type {
+                header.push_str(&format!("Content-Type: {}\r\n", content_type));
+            }
+            header.push_str(&format!(
+                "Content-Range: bytes {}-{}/{}\r\n\r\n",
+                start, end, len
+            ));
+            segments.push_back(Segment::Bytes(header.into_bytes()));
+            segments.push_back(Segment::Range(start, end));
+            segments.push_back(Segment::Bytes(b"\r\n".to_vec()));
+        }
+        segments.push_back(Segment::Bytes(format!("--{}--\r\n", boundary).into_bytes()));
+        let length = segments
+            .iter()
+            .map(|segment| match *segment {
+                Segment::Bytes(ref bytes) => bytes.len() as u64,
+                Segment::Range(start, end) => end - start + 1,
+            })
+            .sum();
+        Multipart {
+            content,
+            segments,
+            length,
+        }
+    }
+
+    /// Length of the whole body
+    pub fn content_length(&self) -> u64 {
+        self.length
+    }
+}
+
+impl<R: Read + Seek> Read for Multipart<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = match self.segments.front_mut() {
+                None => return Ok(0),
+                Some(&mut Segment::Bytes(ref mut bytes)) => {
+                    let read = cmp::min(bytes.len(), buf.len());
+                    buf[..read].copy_from_slice(&bytes[..read]);
+                    bytes.drain(..read);
+                    read
+                }
+                Some(&mut Segment::Range(ref mut start, end)) if *start <= end => {
+                    self.content.seek(SeekFrom::Start(*start))?;
+                    let read = (&mut self.content).take(end - *start + 1).read(buf)?;
+                    if read == 0 && !buf.is_empty() {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    *start += read as u64;
+                    read
+                }
+                Some(&mut Segment::Range(..)) => 0,
+            };
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            self.segments.pop_front();
+        }
+    }
+}
@@ -1,122 +1,1045 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-use std::path::{Path, PathBuf};
+use std::io::{Cursor, SeekFrom};
+use std::net;
+use std::net::SocketAddr;
+use std::panic;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use futures;
-use futures::future::FutureResult;
+use futures::sync::{mpsc, oneshot};
+use futures::{Async, Future, Poll, Sink, Stream};
 use hyper;
-use hyper::header::ContentLength;
+use hyper::client::{Client, HttpConnector};
+use hyper::header;
+use hyper::header::{
+    AcceptRanges, CacheControl, CacheDirective, ContentEncoding, ContentLength, ContentRange,
+    ContentRangeSpec, ETag, EntityTag, IfModifiedSince, IfNoneMatch, LastModified, Location,
+    RangeUnit,
+};
 use hyper::server::{Http, Service};
-use hyper::{Body, Get, Request, Response, StatusCode};
-
-fn default_html_index(target: &str) -> String {
-    return format!(
-        r#"
-        <html>
-            <head>
-                <meta content="text/html;charset=utf-8" http-equiv="Content-Type"/>
-                <script src='./{target}.js'></script>
-                <script>
-                    window.addEventListener('load', function() {{
-                        wasm_bindgen("./{target}_bg.wasm").then(function() {{
-                            wasm_bindgen.wasm.main();
-                        }});
-                    }}, false);
-                </script>
-            </head>
-            <body>
-            </body>
-        </html>"#,
-        target = target
-    );
+use hyper::{Body, Get, Post, Request, Response, StatusCode};
+use openssl::ssl::SslAcceptor;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Core, Handle};
+use tokio_io::io::shutdown;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use build;
+use build::TargetPackage;
+use compress;
+use compress::Encoding;
+use console;
+use console::ConsoleMessage;
+use diagnostics;
+use headers;
+pub use headers::HeaderRule;
+use html;
+use imports;
+use listing;
+pub use logging::LogFormat;
+use logging::{Logger, RequestLog};
+use proxy;
+pub use proxy::ProxyRule;
+use range;
+use range::{Multipart, Ranges};
+use serde_json;
+use symbolicate;
+use symbolicate::Symbols;
+use tls;
+pub use tls::{Error as TlsError, Identity};
+
+// Files larger than this are streamed to the client in chunks of this size
+const STREAM_CHUNK_SIZE: u64 = 64 * 1024;
+
+// How a file is sent in the negotiated encoding
+enum Representation {
+    File(PathBuf),
+    Compressed(Encoding),
 }
 
-fn path_exists(path: &Path) -> bool {
-    match fs::metadata(path) {
-        Err(e) => match e.kind() {
-            io::ErrorKind::NotFound => false,
-            _ => true,
+// Picks the preferred accepted encoding, using precompressed siblings when up to date and
+// otherwise compressing compressible files on the fly
+fn representation(path: &Path, encoding: Option<Encoding>) -> (Representation, Option<Encoding>) {
+    match encoding {
+        Some(encoding) => match compress::precompressed(path, encoding) {
+            Some(precompressed) => (Representation::File(precompressed), Some(encoding)),
+            None if compress::is_compressible(path) => {
+                (Representation::Compressed(encoding), Some(encoding))
+            }
+            None => (Representation::File(path.to_path_buf()), None),
         },
-        _ => true,
+        None => (Representation::File(path.to_path_buf()), None),
     }
 }
 
-fn serve_file(path: &Path) -> Response {
-    if path_exists(path) {
-        let contents = {
-            let mut file = File::open(path).unwrap();
-            let mut buf = Vec::new();
-            file.read_to_end(&mut buf).unwrap();
-            buf
+// Validator derived from the size and modification time of the file, per encoding
+fn entity_tag(
+    metadata: &fs::Metadata,
+    modified: SystemTime,
+    encoding: Option<Encoding>,
+) -> EntityTag {
+    let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut tag = format!(
+        "{:x}-{:x}.{:x}",
+        metadata.len(),
+        modified.as_secs(),
+        modified.subsec_nanos()
+    );
+    if let Some(encoding) = encoding {
+        tag.push('-');
+        tag.push_str(encoding.name());
+    }
+    EntityTag::new(false, tag)
+}
+
+fn is_not_modified(req: &Request, etag: &EntityTag, modified: SystemTime) -> bool {
+    if let Some(if_none_match) = req.headers().get::<IfNoneMatch>() {
+        return match *if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(ref tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+    if let Some(&IfModifiedSince(since)) = req.headers().get::<IfModifiedSince>() {
+        let seconds = |time: SystemTime| time.duration_since(UNIX_EPOCH).map(|d| d.as_secs());
+        return match (seconds(modified), seconds(SystemTime::from(since))) {
+            (Ok(modified), Ok(since)) => modified <= since,
+            _ => false,
         };
-        let mut response = Response::new().with_header(ContentLength(contents.len() as u64));
-        if path.as_os_str().to_str().unwrap().ends_with(".wasm") {
-            response = response.with_header(CustomContentType("application/wasm".to_string()));
+    }
+    false
+}
+
+// Chunks of a reader, read on the event loop as the client takes them
+struct Chunks<R>(Option<R>);
+
+impl<R: Read> Stream for Chunks<R> {
+    type Item = Result<hyper::Chunk, hyper::Error>;
+    type Error = mpsc::SendError<Result<hyper::Chunk, hyper::Error>>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let reader = match self.0 {
+            Some(ref mut reader) => reader,
+            None => return Ok(Async::Ready(None)),
+        };
+        let mut buf = vec![0; STREAM_CHUNK_SIZE as usize];
+        let chunk = match reader.read(&mut buf) {
+            Ok(0) => None,
+            Ok(read) => {
+                buf.truncate(read);
+                Some(Ok(hyper::Chunk::from(buf)))
+            }
+            Err(e) => Some(Err(hyper::Error::Io(e))),
+        };
+        // Nothing is read after the end or an error
+        if !matches!(chunk, Some(Ok(_))) {
+            self.0 = None;
+        }
+        Ok(Async::Ready(chunk))
+    }
+}
+
+// Sends the contents of a reader over a body channel from the event loop
+fn stream<R: Read + 'static>(reader: R, handle: &Handle) -> Body {
+    let (sender, body) = Body::pair();
+    // Stops when the client goes away
+    handle.spawn(sender.send_all(Chunks(Some(reader))).then(|_| Ok(())));
+    body
+}
+
+// The body of a response, counting the bytes hyper takes from it to send. Once hyper is done
+// with it, after its end or when the client went away, the request is logged.
+struct LoggedBody {
+    body: Body,
+    log: Option<(RequestLog, Instant, Rc<AppConfig>)>,
+}
+
+impl From<Body> for LoggedBody {
+    fn from(body: Body) -> LoggedBody {
+        LoggedBody { body, log: None }
+    }
+}
+
+impl Stream for LoggedBody {
+    type Item = hyper::Chunk;
+    type Error = hyper::Error;
+
+    fn poll(&mut self) -> Poll<Option<hyper::Chunk>, hyper::Error> {
+        let chunk = self.body.poll()?;
+        if let (Async::Ready(Some(ref chunk)), Some((ref mut request_log, _, _))) =
+            (&chunk, &mut self.log)
+        {
+            request_log.bytes += chunk.len() as u64;
+        }
+        Ok(chunk)
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        if let Some((request_log, start, config)) = self.log.take() {
+            log_request(request_log, start, &config);
+        }
+    }
+}
+
+fn log_request(mut request_log: RequestLog, start: Instant, config: &AppConfig) {
+    request_log.latency = start.elapsed();
+    config.logger.log(&request_log);
+}
+
+// Logs a request once its response is sent
+fn log_response(
+    response: Response,
+    mut request_log: RequestLog,
+    start: Instant,
+    config: Rc<AppConfig>,
+) -> Response<LoggedBody> {
+    request_log.status = response.status().as_u16();
+    let logged = Response::new()
+        .with_status(response.status())
+        .with_headers(response.headers().clone());
+    // Without a body, the response is complete with its head
+    if response.body_ref().is_none() {
+        log_request(request_log, start, &config);
+        return logged;
+    }
+    logged.with_body(LoggedBody {
+        body: response.body(),
+        log: Some((request_log, start, config)),
+    })
+}
+
+// Content of a response in its negotiated encoding
+enum Content {
+    File(File, u64),
+    Bytes(Vec<u8>),
+}
+
+impl Content {
+    fn open(representation: Representation, path: &Path) -> io::Result<Content> {
+        match representation {
+            Representation::File(path) => {
+                let file = File::open(path)?;
+                let len = file.metadata()?.len();
+                Ok(Content::File(file, len))
+            }
+            Representation::Compressed(encoding) => {
+                Ok(Content::Bytes(compress::compress_cached(path, encoding)?))
+            }
+        }
+    }
+
+    fn len(&self) -> u64 {
+        match *self {
+            Content::File(_, len) => len,
+            Content::Bytes(ref bytes) => bytes.len() as u64,
+        }
+    }
+
+    // Reads an inclusive byte range into memory
+    fn read_range(&mut self, (start, end): (u64, u64)) -> io::Result<Vec<u8>> {
+        match *self {
+            Content::File(ref mut file, _) => {
+                let mut buf = Vec::new();
+                file.seek(SeekFrom::Start(start))?;
+                file.take(end - start + 1).read_to_end(&mut buf)?;
+                Ok(buf)
+            }
+            Content::Bytes(ref bytes) => Ok(bytes[start as usize..=end as usize].to_vec()),
+        }
+    }
+
+    // Body with the whole content or a single inclusive byte range, large files are streamed
+    fn into_body(mut self, range: Option<(u64, u64)>, handle: &Handle) -> io::Result<Body> {
+        let (start, end) = range.unwrap_or((0, self.len().saturating_sub(1)));
+        match self {
+            Content::File(mut file, _) if end - start >= STREAM_CHUNK_SIZE => {
+                file.seek(SeekFrom::Start(start))?;
+                Ok(stream(file.take(end - start + 1), handle))
+            }
+            _ if self.len() == 0 => Ok(Body::empty()),
+            _ => Ok(self.read_range((start, end))?.into()),
+        }
+    }
+
+    // `multipart/byteranges` body with each range and its length, files are streamed
+    fn into_multipart_body(
+        self,
+        boundary: &str,
+        content_type: Option<&str>,
+        ranges: &[(u64, u64)],
+        handle: &Handle,
+    ) -> io::Result<(u64, Body)> {
+        let len = self.len();
+        match self {
+            Content::File(file, _) => {
+                let multipart = Multipart::new(file, boundary, content_type, len, ranges);
+                Ok((multipart.content_length(), stream(multipart, handle)))
+            }
+            Content::Bytes(bytes) => {
+                let mut multipart =
+                    Multipart::new(Cursor::new(bytes), boundary, content_type, len, ranges);
+                let mut body = Vec::new();
+                multipart.read_to_end(&mut body)?;
+                Ok((body.len() as u64, body.into()))
+            }
+        }
+    }
+}
+
+fn file_response(path: &Path, req: &Request, handle: &Handle) -> io::Result<Response> {
+    let metadata = fs::metadata(path)?;
+    if metadata.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "path is a directory",
+        ));
+    }
+    let modified = metadata.modified()?;
+
+    let accept_encoding = header_value(req, "Accept-Encoding");
+    let (representation, encoding) = representation(
+        path,
+        accept_encoding.as_deref().and_then(compress::negotiate),
+    );
+    let etag = entity_tag(&metadata, modified, encoding);
+
+    let mut response = Response::new()
+        .with_header(ETag(etag.clone()))
+        .with_header(LastModified(modified.into()))
+        .with_header(CacheControl(vec![CacheDirective::NoCache]));
+    if compress::is_compressible(path) {
+        response.headers_mut().set_raw("Vary", "Accept-Encoding");
+    }
+    if is_not_modified(req, &etag, modified) {
+        return Ok(response.with_status(StatusCode::NotModified));
+    }
+
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "wasm")
+    {
+        response = response.with_header(CustomContentType("application/wasm".to_string()));
+    }
+    if let Some(encoding) = encoding {
+        let encoding = match encoding {
+            Encoding::Brotli => header::Encoding::Brotli,
+            Encoding::Gzip => header::Encoding::Gzip,
+        };
+        response = response.with_header(ContentEncoding(vec![encoding]));
+    }
+    let content = Content::open(representation, path)?;
+    let len = content.len();
+    response = response.with_header(AcceptRanges(vec![RangeUnit::Bytes]));
+    match range::requested(req, &etag, modified, len) {
+        Ranges::Full => Ok(response
+            .with_header(ContentLength(len))
+            .with_body(content.into_body(None, handle)?)),
+        Ranges::Unsatisfiable => Ok(response
+            .with_status(StatusCode::RangeNotSatisfiable)
+            .with_header(ContentRange(ContentRangeSpec::Bytes {
+                range: None,
+                instance_length: Some(len),
+            }))
+            .with_header(ContentLength(0))),
+        Ranges::Partial(ref ranges) if ranges.len() == 1 => {
+            let (start, end) = ranges[0];
+            Ok(response
+                .with_status(StatusCode::PartialContent)
+                .with_header(ContentRange(ContentRangeSpec::Bytes {
+                    range: Some((start, end)),
+                    instance_length: Some(len),
+                }))
+                .with_header(ContentLength(end - start + 1))
+                .with_body(content.into_body(Some((start, end)), handle)?))
+        }
+        Ranges::Partial(ranges) => {
+            let content_type = response
+                .headers()
+                .get::<CustomContentType>()
+                .map(|content_type| content_type.0.clone());
+            let boundary = range::boundary(&etag);
+            let (length, body) =
+                content.into_multipart_body(&boundary, content_type.as_deref(), &ranges, handle)?;
+            Ok(response
+                .with_status(StatusCode::PartialContent)
+                .with_header(CustomContentType(format!(
+                    "multipart/byteranges; boundary={}",
+                    boundary
+                )))
+                .with_header(ContentLength(length))
+                .with_body(body))
+        }
+    }
+}
+
+fn serve_file(path: &Path, req: &Request, handle: &Handle) -> Response {
+    match file_response(path, req, handle) {
+        Ok(response) => response,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            Response::new().with_status(StatusCode::NotFound)
+        }
+        Err(e) => {
+            println!("wasm-bin: Failed to serve {}: {}", path.display(), e);
+            Response::new().with_status(StatusCode::InternalServerError)
         }
-        response.with_body(contents)
-    } else {
-        Response::new().with_status(StatusCode::NotFound)
     }
 }
 
+// Value of a request header, with repeated header lines joined by commas
+fn header_value(req: &Request, name: &str) -> Option<String> {
+    req.headers().get_raw(name).map(|raw| {
+        raw.iter()
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect::<Vec<_>>()
+            .join(", ")
+    })
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Maps a path within a target to a file in its directory, or `None` if the path would leave
+// the directory. Parts are decoded before being checked, so encoded separators can't sneak
+// in `..` or absolute paths.
+fn resolve_path(app_path: &Path, path: &str) -> Option<PathBuf> {
+    let mut file_path = app_path.to_path_buf();
+    for part in path.split('/').filter(|part| !part.is_empty()) {
+        let part = percent_decode(part);
+        if part.contains(['/', '\\', '\0']) {
+            return None;
+        }
+        for component in Path::new(&part).components() {
+            match component {
+                Component::Normal(name) => file_path.push(name),
+                _ => return None,
+            }
+        }
+    }
+    // Symlinks may still point outside of the directory
+    match (file_path.canonicalize(), app_path.canonicalize()) {
+        (Ok(file), Ok(app)) if !file.starts_with(&app) => None,
+        _ => Some(file_path),
+    }
+}
+
+// Whether a request looks like a browser navigation rather than a request for an asset: the
+// path has no file extension and the client accepts html
+fn is_navigation(req: &Request) -> bool {
+    let last_segment = req.path().rsplit('/').next().unwrap_or("");
+    let accepts_html = header_value(req, "Accept")
+        .map(|accept| accept.contains("text/html"))
+        .unwrap_or(false);
+    !last_segment.contains('.') && accepts_html
+}
+
 header! { (CustomContentType, "Content-Type") => [String] }
-struct WebApp {
-    target: String,
+
+// A target served under `/<name>/`
+struct Target {
+    name: String,
     app_path: PathBuf,
 }
 
+impl Target {
+    fn wasm_path(&self) -> PathBuf {
+        self.app_path.join(format!("{}_bg.wasm", self.name))
+    }
+}
+
+// State shared by the connections of a server
+struct AppConfig {
+    targets: Vec<Target>,
+    /// Page describing a failed build, served instead of the targets
+    build_error: Option<String>,
+    entry: html::Entry,
+    headers: Vec<HeaderRule>,
+    proxy_rules: Vec<ProxyRule>,
+    spa: bool,
+    list_dirs: bool,
+    forward_console: bool,
+    logger: Logger,
+}
+
+// Symbols of the wasm modules of targets, kept until a module changes. Loading them reads and
+// parses the whole module and its DWARF.
+#[derive(Default)]
+struct SymbolCache(RefCell<HashMap<String, (SystemTime, Rc<Symbols>)>>);
+
+impl SymbolCache {
+    fn load(&self, target: &Target) -> Result<Rc<Symbols>, symbolicate::Error> {
+        let path = target.wasm_path();
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(symbolicate::Error::ReadWasmError)?;
+        if let Some((cached, symbols)) = self.0.borrow().get(&target.name) {
+            if *cached == modified {
+                return Ok(symbols.clone());
+            }
+        }
+        let symbols = Rc::new(Symbols::load(&path)?);
+        self.0
+            .borrow_mut()
+            .insert(target.name.clone(), (modified, symbols.clone()));
+        Ok(symbols)
+    }
+
+    // Resolves the wasm frames of a stack trace using the wasm module of a target
+    fn symbolicate(&self, config: &AppConfig, target: &str, trace: &str) -> String {
+        if !symbolicate::has_wasm_frames(trace) {
+            return trace.to_string();
+        }
+        let target = match config.targets.iter().find(|t| t.name == target) {
+            Some(target) => target,
+            None => return trace.to_string(),
+        };
+        match self.load(target) {
+            Ok(symbols) => symbols.symbolicate(trace),
+            Err(e) => {
+                println!(
+                    "wasm-bin: Failed to read symbols of '{}': {:?}",
+                    target.name, e
+                );
+                trace.to_string()
+            }
+        }
+    }
+}
+
+// Body of a request to the symbolicate endpoint
+#[derive(Deserialize)]
+struct SymbolicateRequest {
+    target: String,
+    trace: String,
+}
+
+struct WebApp {
+    config: Rc<AppConfig>,
+    symbols: Rc<SymbolCache>,
+    client: Client<HttpConnector>,
+    connector: HttpConnector,
+    handle: Handle,
+    https: bool,
+    remote_addr: SocketAddr,
+    // Upstream connection to tunnel to once a proxied upgrade is answered
+    upgraded: proxy::UpgradeSlot,
+}
+
+// Redirects a directory path to have a trailing slash, so relative links resolve inside it
+fn redirect_to_directory(req: &Request) -> Response {
+    let location = match req.query() {
+        Some(query) => format!("{}/?{}", req.path(), query),
+        None => format!("{}/", req.path()),
+    };
+    Response::new()
+        .with_status(StatusCode::MovedPermanently)
+        .with_header(Location::new(location))
+}
+
+impl WebApp {
+    // The target a request path belongs to, the path it is mounted at and the path within it.
+    // Paths outside of any `/<name>/` belong to the first target, mounted at `/`.
+    fn route<'a>(&self, path: &'a str) -> (&Target, &'a str, &'a str) {
+        for target in &self.config.targets {
+            let mount_len = target.name.len() + 1;
+            let is_mount = path.len() >= mount_len
+                && path[1..].starts_with(target.name.as_str())
+                && (path.len() == mount_len || path[mount_len..].starts_with('/'));
+            if is_mount {
+                return (target, &path[..mount_len], &path[mount_len..]);
+            }
+        }
+        (&self.config.targets[0], "", path)
+    }
+
+    fn html_context(&self, target: &Target, mount: &str) -> html::Context {
+        let mut context = html::Context::new(&target.name);
+        context.entry = self.config.entry.clone();
+        context.import_stubs = target
+            .app_path
+            .join(imports::stubs_file_name(&target.name))
+            .is_file();
+        // The index is also served for nested paths, where relative paths would break
+        if self.config.spa {
+            context.base = format!("{}/", mount);
+        }
+        context
+    }
+
+    fn index(&self, target: &Target, mount: &str) -> Response {
+        match html::render_index(&self.html_context(target, mount)) {
+            Ok(contents) => {
+                let contents = if self.config.forward_console {
+                    html::inject_head(&contents, &html::console_script(&target.name))
+                } else {
+                    contents
+                };
+                Response::new()
+                    .with_header(ContentLength(contents.len() as u64))
+                    .with_body(contents)
+            }
+            Err(e) => {
+                println!("wasm-bin: Failed to render html index: {:?}", e);
+                Response::new().with_status(StatusCode::InternalServerError)
+            }
+        }
+    }
+
+    // Redirects directory paths to have a trailing slash, then serves the directory's
+    // index.html or, if enabled, a listing
+    fn serve_directory(&self, dir: &Path, req: &Request) -> Response {
+        if !req.path().ends_with('/') {
+            return redirect_to_directory(req);
+        }
+        let index = dir.join("index.html");
+        if index.is_file() || !self.config.list_dirs {
+            return serve_file(&index, req, &self.handle);
+        }
+        match listing::render(dir, req.path()) {
+            Ok(contents) => Response::new()
+                .with_header(ContentLength(contents.len() as u64))
+                .with_body(contents),
+            Err(e) => {
+                println!("wasm-bin: Failed to list {}: {}", dir.display(), e);
+                Response::new().with_status(StatusCode::InternalServerError)
+            }
+        }
+    }
+
+    fn respond(&self, req: &Request) -> Response {
+        if let Some(ref page) = self.config.build_error {
+            return Response::new()
+                .with_status(StatusCode::InternalServerError)
+                .with_header(CustomContentType("text/html; charset=utf-8".to_string()))
+                .with_header(ContentLength(page.len() as u64))
+                .with_body(page.clone());
+        }
+        let (target, mount, path) = self.route(req.path());
+        match (req.method(), path) {
+            (&Get, "") => redirect_to_directory(req),
+            (&Get, "/") => self.index(target, mount),
+            (&Get, path) => {
+                let file_path = match resolve_path(&target.app_path, path) {
+                    Some(file_path) => file_path,
+                    None => return Response::new().with_status(StatusCode::BadRequest),
+                };
+
+                let response = if file_path.is_dir() {
+                    self.serve_directory(&file_path, req)
+                } else {
+                    serve_file(&file_path, req, &self.handle)
+                };
+                if response.status() == StatusCode::NotFound
+                    && self.config.spa
+                    && is_navigation(req)
+                {
+                    self.index(target, mount)
+                } else {
+                    response
+                }
+            }
+            _ => Response::new().with_status(StatusCode::NotFound),
+        }
+    }
+}
+
 impl Service for WebApp {
     type Request = Request<Body>;
-    type Response = Response<Body>;
+    type Response = Response<LoggedBody>;
     type Error = hyper::Error;
-    type Future = FutureResult<Self::Response, hyper::Error>;
+    type Future = Box<dyn Future<Item = Self::Response, Error = hyper::Error>>;
 
     fn call(&self, req: Request) -> Self::Future {
-        futures::future::ok(match (req.method(), req.path()) {
-            (&Get, "/") => {
-                let html_index = format!("./html/{}.html", self.target);
-                let html_index_path = Path::new(&html_index);
-                if path_exists(html_index_path) {
-                    serve_file(html_index_path)
-                } else {
-                    let contents = default_html_index(&self.target);
-                    Response::new()
-                        .with_header(ContentLength(contents.len() as u64))
-                        .with_body(contents)
+        if req.method() == &Post && req.path() == symbolicate::ENDPOINT {
+            let (config, symbols) = (self.config.clone(), self.symbols.clone());
+            return Box::new(req.body().concat2().map(move |body| {
+                match serde_json::from_slice::<SymbolicateRequest>(&body) {
+                    Ok(request) => {
+                        let trace = symbols.symbolicate(&config, &request.target, &request.trace);
+                        Response::new()
+                            .with_header(ContentLength(trace.len() as u64))
+                            .with_body(Body::from(trace))
+                    }
+                    Err(e) => Response::new()
+                        .with_status(StatusCode::BadRequest)
+                        .with_body(Body::from(e.to_string())),
                 }
-            }
-            (&Get, path) => {
-                let mut file_path = self.app_path.clone();
-                for (i, path_part) in path.split("/").enumerate() {
-                    if i > 0 {
-                        file_path.push(Path::new(path_part));
+            }));
+        }
+        if self.config.forward_console && req.method() == &Post && req.path() == console::ENDPOINT {
+            let (config, symbols) = (self.config.clone(), self.symbols.clone());
+            return Box::new(req.body().concat2().map(move |body| {
+                match serde_json::from_slice::<ConsoleMessage>(&body) {
+                    Ok(mut message) => {
+                        message.message =
+                            symbols.symbolicate(&config, &message.target, &message.message);
+                        console::print(&message);
                     }
+                    Err(e) => println!("wasm-bin: Invalid console message: {}", e),
                 }
+                Response::new().with_status(StatusCode::NoContent)
+            }));
+        }
+        let start = Instant::now();
+        let path = req.path().to_string();
+        let request_log = RequestLog {
+            time: SystemTime::now(),
+            remote_addr: self.remote_addr,
+            method: req.method().to_string(),
+            uri: req.uri().to_string(),
+            version: req.version().to_string(),
+            referer: header_value(&req, "Referer"),
+            user_agent: header_value(&req, "User-Agent"),
+            status: 0,
+            bytes: 0,
+            latency: Duration::default(),
+        };
+        let config = self.config.clone();
+        let response = match proxy::find(&self.config.proxy_rules, &path) {
+            Some(rule) if proxy::is_upgrade(&req) => proxy::upgrade(
+                &self.connector,
+                &self.handle,
+                rule,
+                req,
+                self.https,
+                self.upgraded.clone(),
+            ),
+            Some(rule) => proxy::forward(&self.client, rule, req, self.https),
+            None => Box::new(futures::future::ok(self.respond(&req))),
+        };
+        Box::new(response.map(move |response| {
+            let response = headers::apply(&config.headers, &path, response);
+            log_response(response, request_log, start, config)
+        }))
+    }
+}
 
-                serve_file(&file_path)
+// Serves http on a connection. Once a response switched protocols for a proxied upgrade, hyper
+// hands the connection back and it is tunneled to the upstream.
+fn serve_connection<S>(
+    http: &Http,
+    app: WebApp,
+    stream: S,
+) -> Box<dyn Future<Item = (), Error = ()>>
+where
+    S: AsyncRead + AsyncWrite + 'static,
+{
+    let mut connection = Some(http.serve_connection(stream, app));
+    let done = futures::future::poll_fn(move || {
+        match connection.as_mut().unwrap().poll_without_shutdown()? {
+            Async::Ready(()) => Ok(Async::Ready(connection.take().unwrap().into_parts())),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    });
+    Box::new(done.map_err(|_: hyper::Error| ()).and_then(|parts| {
+        let upgraded = parts.service.upgraded.borrow_mut().take();
+        match upgraded {
+            Some(upstream) => {
+                let client = proxy::Rewind::new(parts.read_buf.to_vec(), parts.io);
+                Box::new(proxy::tunnel(client, upstream).map_err(|_| ()))
+                    as Box<dyn Future<Item = (), Error = ()>>
             }
-            _ => Response::new().with_status(StatusCode::NotFound),
-        })
+            None => Box::new(shutdown(parts.io).map(|_| ()).map_err(|_| ())),
+        }
+    }))
+}
+
+#[derive(Debug)]
+pub enum Error {
+    NoTargetsError,
+    CreateEventLoopError(io::Error),
+    BindError(io::Error),
+    TlsError(tls::Error),
+    OpenLogFileError(io::Error),
+    ServerError(io::Error),
+}
+
+pub struct Options {
+    /// Address to listen on, defaults to 127.0.0.1:8000. Port 0 picks a free port.
+    pub addr: Option<SocketAddr>,
+    pub entry: html::Entry,
+    pub https: bool,
+    /// Certificate and key to serve https with, a cached self-signed certificate is used if unset
+    pub tls_identity: Option<Identity>,
+    /// Directory the self-signed certificate is cached in, ./target/wasm-bin/certs if unset
+    pub cert_dir: Option<PathBuf>,
+    /// Serve with the headers required for `SharedArrayBuffer` and wasm threads
+    pub cross_origin_isolated: bool,
+    pub headers: Vec<HeaderRule>,
+    pub proxy_rules: Vec<ProxyRule>,
+    /// Serve the index page for unknown navigation paths, for apps with client-side routing
+    pub spa: bool,
+    /// Render listings of directories without an index.html
+    pub list_dirs: bool,
+    /// Inject a script into the index page that prints its console output and uncaught errors
+    /// in the terminal
+    pub forward_console: bool,
+    pub log_format: LogFormat,
+    /// File to append request logs to instead of printing them
+    pub log_file: Option<PathBuf>,
+}
+
+// Defaults match those of `wasm-bin run`
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            addr: None,
+            entry: html::Entry::default(),
+            https: false,
+            tls_identity: None,
+            cert_dir: None,
+            cross_origin_isolated: false,
+            headers: Vec::new(),
+            proxy_rules: Vec::new(),
+            spa: false,
+            list_dirs: false,
+            forward_console: true,
+            log_format: LogFormat::default(),
+            log_file: None,
+        }
+    }
+}
+
+fn tls_acceptor(options: &Options) -> Result<SslAcceptor, tls::Error> {
+    let identity = match options.tls_identity {
+        Some(ref identity) => identity,
+        None => &tls::self_signed_identity(
+            options
+                .cert_dir
+                .as_deref()
+                .unwrap_or_else(|| Path::new(tls::CERT_DIR)),
+        )?,
+    };
+    println!(
+        "wasm-bin: TLS certificate {} (SHA-256 fingerprint {})",
+        identity.cert.display(),
+        tls::fingerprint(&identity.cert)?
+    );
+    tls::acceptor(identity)
+}
+
+/// Asks a running server to shut down. Can be cloned and sent to other threads, e.g. a signal
+/// handler.
+#[derive(Clone)]
+pub struct Shutdown(Arc<Mutex<Option<oneshot::Sender<()>>>>);
+
+impl Shutdown {
+    pub fn shutdown(&self) {
+        if let Some(sender) = self.0.lock().unwrap().take() {
+            let _ = sender.send(());
+        }
+    }
+}
+
+/// Handle to a server running on a background thread, which is shut down when dropped
+pub struct Server {
+    addr: SocketAddr,
+    shutdown: Shutdown,
+    thread: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl Server {
+    /// The address the server is listening on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Stops accepting connections, closes open connections and waits for the server to stop
+    pub fn shutdown(mut self) -> Result<(), Error> {
+        self.shutdown.shutdown();
+        self.join()
+    }
+
+    /// Blocks until the server stops, either because it failed or was shut down through a
+    /// `Shutdown` handle
+    pub fn wait(mut self) -> Result<(), Error> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), Error> {
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|panic| panic::resume_unwind(panic)),
+            None => Ok(()),
+        }
     }
 }
 
-pub fn serve(target_name: String, mut path: PathBuf) {
-    let addr = "127.0.0.1:8000".parse().unwrap();
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shutdown.shutdown();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
 
-    path.pop();
-    let server = Http::new()
-        .bind(&addr, move || {
-            Ok(WebApp {
-                target: target_name.clone(),
-                app_path: path.clone(),
-            })
+/// Starts serving the outputs of built targets on a background thread.
+///
+/// Each target is served under `/<name>/`, and the first target is also served at `/`.
+pub fn serve(targets: &[TargetPackage], options: &Options) -> Result<Server, Error> {
+    if targets.is_empty() {
+        return Err(Error::NoTargetsError);
+    }
+    let targets = targets
+        .iter()
+        .map(|target| Target {
+            name: target.name.clone(),
+            app_path: target
+                .path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
         })
-        .unwrap();
+        .collect();
+    start(targets, None, options)
+}
+
+/// Starts serving a page describing a failed build, with its compiler diagnostics, at every
+/// route except proxied ones. The page is served with status 500 so automated checks notice.
+pub fn serve_build_error(error: &build::Error, options: &Options) -> Result<Server, Error> {
+    start(Vec::new(), Some(diagnostics::render(error)), options)
+}
+
+fn start(
+    targets: Vec<Target>,
+    build_error: Option<String>,
+    options: &Options,
+) -> Result<Server, Error> {
+    let acceptor = if options.https {
+        Some(tls_acceptor(options).map_err(Error::TlsError)?)
+    } else {
+        None
+    };
+
+    let addr = options
+        .addr
+        .unwrap_or_else(|| "127.0.0.1:8000".parse().unwrap());
+    let listener = net::TcpListener::bind(addr).map_err(Error::BindError)?;
+    let addr = listener.local_addr().map_err(Error::BindError)?;
     println!(
-        "wasm-bin: Listening on http://{}.",
-        server.local_addr().unwrap()
+        "wasm-bin: Listening on {}://{}.",
+        if acceptor.is_some() { "https" } else { "http" },
+        addr
     );
-    server.run().unwrap();
+
+    let mut header_rules = Vec::new();
+    if options.cross_origin_isolated {
+        header_rules.extend(headers::cross_origin_isolation());
+    }
+    header_rules.extend(options.headers.iter().cloned());
+    let logger = match options.log_file {
+        Some(ref path) => {
+            Logger::file(options.log_format, path).map_err(Error::OpenLogFileError)?
+        }
+        None => Logger::stdout(options.log_format),
+    };
+    let config = AppConfig {
+        targets,
+        build_error,
+        entry: options.entry.clone(),
+        headers: header_rules,
+        proxy_rules: options.proxy_rules.clone(),
+        spa: options.spa,
+        list_dirs: options.list_dirs,
+        forward_console: options.forward_console,
+        logger,
+    };
+
+    let (sender, receiver) = oneshot::channel();
+    let thread = thread::spawn(move || run(listener, addr, config, acceptor, receiver));
+    Ok(Server {
+        addr,
+        shutdown: Shutdown(Arc::new(Mutex::new(Some(sender)))),
+        thread: Some(thread),
+    })
+}
+
+// Runs the event loop of a server until it fails or is shut down
+fn run(
+    listener: net::TcpListener,
+    addr: SocketAddr,
+    config: AppConfig,
+    acceptor: Option<SslAcceptor>,
+    shutdown: oneshot::Receiver<()>,
+) -> Result<(), Error> {
+    let mut core = Core::new().map_err(Error::CreateEventLoopError)?;
+    let handle = core.handle();
+    let listener =
+        TcpListener::from_listener(listener, &addr, &handle).map_err(Error::BindError)?;
+    let config = Rc::new(config);
+    let symbols = Rc::new(SymbolCache::default());
+    // Upstream connections of proxied requests and upgrades share a resolver thread pool
+    let connector = HttpConnector::new(4, &handle);
+    let client = Client::configure()
+        .connector(connector.clone())
+        .build(&handle);
+
+    let http = Http::<hyper::Chunk>::new();
+    let server = listener.incoming().for_each(|(socket, remote_addr)| {
+        let app = WebApp {
+            config: config.clone(),
+            symbols: symbols.clone(),
+            client: client.clone(),
+            connector: connector.clone(),
+            handle: handle.clone(),
+            upgraded: proxy::UpgradeSlot::default(),
+            https: acceptor.is_some(),
+            remote_addr,
+        };
+        match acceptor {
+            Some(ref acceptor) => {
+                let http = http.clone();
+                let connection = tls::accept(acceptor, socket)
+                    .map_err(|e| println!("wasm-bin: TLS handshake failed: {}", e))
+                    .and_then(move |stream| serve_connection(&http, app, stream));
+                handle.spawn(connection);
+            }
+            None => handle.spawn(serve_connection(&http, app, socket)),
+        }
+        Ok(())
+    });
+    // A dropped shutdown sender also stops the server
+    let shutdown = shutdown.then(|_| Ok(()));
+    core.run(server.select(shutdown).map(|_| ()).map_err(|(e, _)| e))
+        .map_err(Error::ServerError)
 }
@@ -0,0 +1,171 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use build;
+use html;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidNameError(String),
+    ExistingDirectoryError(PathBuf),
+    ExistingManifestError(PathBuf),
+    CreateDirError(io::Error),
+    WriteFileError(PathBuf, io::Error),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Template {
+    /// A binary whose `main` the page calls
+    #[default]
+    Bin,
+    /// A cdylib library exporting its entry with `#[wasm_bindgen]`
+    Lib,
+}
+
+#[derive(Default)]
+pub struct Options {
+    /// Package name, defaults to the name of the directory
+    pub name: Option<String>,
+    pub template: Template,
+    /// Also write an html/<target>.html template to customize
+    pub html: bool,
+    /// Also create a static folder, whose files are copied next to the outputs
+    pub static_dir: bool,
+}
+
+const BIN_SOURCE: &str = r#"use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console)]
+    fn log(s: &str);
+}
+
+// Called by the page once the module is loaded
+fn main() {
+    log("Hello from {name}!");
+}
+"#;
+
+const LIB_SOURCE: &str = r#"use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console)]
+    fn log(s: &str);
+}
+
+// Called by the page once the module is loaded
+#[wasm_bindgen]
+pub fn main() {
+    log("Hello from {name}!");
+}
+"#;
+
+const STYLE: &str = "body {\n    font-family: sans-serif;\n}\n";
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn manifest(name: &str, template: Template) -> String {
+    let mut manifest = format!(
+        "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n",
+        name
+    );
+    if template == Template::Lib {
+        manifest.push_str("[lib]\ncrate-type = [\"cdylib\"]\n\n");
+    }
+    manifest.push_str("[dependencies]\nwasm-bindgen = \"0.2\"\n");
+    manifest
+}
+
+fn write(dir: &Path, path: &str, contents: &str, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let path = dir.join(path.trim_start_matches("./"));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(Error::CreateDirError)?;
+    }
+    fs::write(&path, contents).map_err(|e| Error::WriteFileError(path.clone(), e))?;
+    files.push(path);
+    Ok(())
+}
+
+// The package name, defaulting to the name of the directory
+fn package_name(dir: &Path, options: &Options) -> Result<String, Error> {
+    let name = match options.name {
+        Some(ref name) => name.clone(),
+        None => dir
+            .canonicalize()
+            .unwrap_or_else(|_| dir.to_path_buf())
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    if is_valid_name(&name) {
+        Ok(name)
+    } else {
+        Err(Error::InvalidNameError(name))
+    }
+}
+
+/// Creates a new project in a directory that doesn't exist yet
+pub fn new(dir: &Path, options: &Options) -> Result<Vec<PathBuf>, Error> {
+    if dir.exists() {
+        return Err(Error::ExistingDirectoryError(dir.to_path_buf()));
+    }
+    package_name(dir, options)?;
+    fs::create_dir_all(dir).map_err(Error::CreateDirError)?;
+    init(dir, options)
+}
+
+/// Creates a new project in an existing directory, returning the written files
+pub fn init(dir: &Path, options: &Options) -> Result<Vec<PathBuf>, Error> {
+    let name = package_name(dir, options)?;
+    let manifest_path = dir.join("Cargo.toml");
+    if manifest_path.exists() {
+        return Err(Error::ExistingManifestError(manifest_path));
+    }
+
+    let mut files = Vec::new();
+    write(
+        dir,
+        "Cargo.toml",
+        &manifest(&name, options.template),
+        &mut files,
+    )?;
+    let (source_path, source, target) = match options.template {
+        Template::Bin => ("src/main.rs", BIN_SOURCE, name.clone()),
+        Template::Lib => ("src/lib.rs", LIB_SOURCE, name.replace('-', "_")),
+    };
+    write(
+        dir,
+        source_path,
+        &source.replace("{name}", &name),
+        &mut files,
+    )?;
+    if !dir.join(".gitignore").exists() {
+        write(dir, ".gitignore", "/target\n", &mut files)?;
+    }
+
+    if options.html {
+        let mut template = html::default_template().to_string();
+        if options.static_dir {
+            template = template.replace(
+                "        {{loader}}\n",
+                "        <link rel=\"stylesheet\" href=\"style.css\"/>\n        {{loader}}\n",
+            );
+        }
+        let template_path = html::template_path(&target);
+        write(dir, &template_path.to_string_lossy(), &template, &mut files)?;
+    }
+    if options.static_dir {
+        let style_path = Path::new(build::STATIC_DIR).join("style.css");
+        write(dir, &style_path.to_string_lossy(), STYLE, &mut files)?;
+    }
+    Ok(files)
+}
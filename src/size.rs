@@ -0,0 +1,269 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use wasmparser::{BinaryReaderError, KnownCustom, Parser, Payload, TypeRef};
+
+use symbolicate;
+use util::format_size;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadWasmError(io::Error),
+    ParseWasmError(BinaryReaderError),
+}
+
+#[derive(Debug, Serialize)]
+pub struct SectionSize {
+    /// Section name, `custom:<name>` for custom sections
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunctionSize {
+    pub index: u32,
+    /// Demangled name, `function[<index>]` if the module has no name for it
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrateSize {
+    pub name: String,
+    /// Total size of the crate's function bodies
+    pub size: u64,
+    pub functions: usize,
+}
+
+/// Where the bytes of a wasm module go
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub total: u64,
+    /// Sections by decreasing size
+    pub sections: Vec<SectionSize>,
+    /// Function bodies by decreasing size
+    pub functions: Vec<FunctionSize>,
+    /// Total size of the data segments
+    pub data: u64,
+    /// Function bodies grouped by the crate they originate from, by decreasing size
+    pub crates: Vec<CrateSize>,
+}
+
+fn section_name(id: u8) -> &'static str {
+    match id {
+        1 => "type",
+        2 => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        10 => "code",
+        11 => "data",
+        12 => "datacount",
+        13 => "tag",
+        _ => "unknown",
+    }
+}
+
+/// The crate a demangled function name belongs to, e.g. `alloc` for `alloc::vec::Vec<T>::push`
+/// or `<alloc::string::String as core::fmt::Display>::fmt`
+pub fn crate_name(function: &str) -> Option<&str> {
+    let path = function.trim_start_matches(['<', '&', '*']);
+    let path = path.strip_prefix("mut ").unwrap_or(path);
+    let path = path.strip_prefix("const ").unwrap_or(path);
+    let end = path.find("::")?;
+    let name = &path[..end];
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+pub fn analyze_file(path: &Path) -> Result<Report, Error> {
+    let module = fs::read(path).map_err(Error::ReadWasmError)?;
+    analyze(&module)
+}
+
+pub fn analyze(module: &[u8]) -> Result<Report, Error> {
+    let mut sections = Vec::new();
+    let mut bodies = Vec::new();
+    let mut imported_functions = 0;
+    let mut data = 0;
+    let mut names = HashMap::new();
+    for payload in Parser::new(0).parse_all(module) {
+        let payload = payload.map_err(Error::ParseWasmError)?;
+        if let Some((id, range)) = payload.as_section() {
+            let name = match payload {
+                Payload::CustomSection(ref section) => format!("custom:{}", section.name()),
+                _ => section_name(id).to_string(),
+            };
+            sections.push(SectionSize {
+                name,
+                size: range.len() as u64,
+            });
+        }
+        match payload {
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    if let TypeRef::Func(_) = import.map_err(Error::ParseWasmError)?.ty {
+                        imported_functions += 1;
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                let index = imported_functions + bodies.len() as u32;
+                bodies.push((index, body.range().len() as u64));
+            }
+            Payload::DataSection(segments) => {
+                for segment in segments {
+                    data += segment.map_err(Error::ParseWasmError)?.data.len() as u64;
+                }
+            }
+            Payload::CustomSection(section) => {
+                if let KnownCustom::Name(reader) = section.as_known() {
+                    names = symbolicate::function_names(reader).map_err(Error::ParseWasmError)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut functions: Vec<FunctionSize> = bodies
+        .into_iter()
+        .map(|(index, size)| FunctionSize {
+            index,
+            name: names
+                .remove(&index)
+                .unwrap_or_else(|| format!("function[{}]", index)),
+            size,
+        })
+        .collect();
+    functions.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.index.cmp(&b.index)));
+
+    let mut crates: HashMap<&str, CrateSize> = HashMap::new();
+    for function in &functions {
+        let name = crate_name(&function.name).unwrap_or("[unknown]");
+        let entry = crates.entry(name).or_insert_with(|| CrateSize {
+            name: name.to_string(),
+            size: 0,
+            functions: 0,
+        });
+        entry.size += function.size;
+        entry.functions += 1;
+    }
+    let mut crates: Vec<CrateSize> = crates.into_values().collect();
+    crates.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+    sections.sort_by_key(|section| Reverse(section.size));
+    Ok(Report {
+        total: module.len() as u64,
+        sections,
+        functions,
+        data,
+        crates,
+    })
+}
+
+fn share(size: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        size as f64 * 100.0 / total as f64
+    }
+}
+
+/// Formats a report as text tables, listing at most `limit` functions and crates
+pub fn table(report: &Report, limit: usize) -> String {
+    let mut out = format!(
+        "Total: {}, data segments: {}\n\n",
+        format_size(report.total),
+        format_size(report.data)
+    );
+    out.push_str(&format!("{:>10} {:>7}  Section\n", "Size", "Share"));
+    for section in &report.sections {
+        out.push_str(&format!(
+            "{:>10} {:>6.1}%  {}\n",
+            format_size(section.size),
+            share(section.size, report.total),
+            section.name
+        ));
+    }
+    out.push_str(&format!("\n{:>10} {:>7}  Function\n", "Size", "Share"));
+    for function in report.functions.iter().take(limit) {
+        out.push_str(&format!(
+            "{:>10} {:>6.1}%  {}\n",
+            format_size(function.size),
+            share(function.size, report.total),
+            function.name
+        ));
+    }
+    out.push_str(&format!(
+        "\n{:>10} {:>7} {:>9}  Crate\n",
+        "Size", "Share", "Functions"
+    ));
+    for krate in report.crates.iter().take(limit) {
+        out.push_str(&format!(
+            "{:>10} {:>6.1}% {:>9}  {}\n",
+            format_size(krate.size),
+            share(krate.size, report.total),
+            krate.functions,
+            krate.name
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_wasm::section;
+
+    #[test]
+    fn crate_of_function() {
+        assert_eq!(crate_name("alloc::vec::Vec<T>::push"), Some("alloc"));
+        assert_eq!(
+            crate_name("<alloc::string::String as core::fmt::Display>::fmt"),
+            Some("alloc")
+        );
+        assert_eq!(crate_name("<&mut T as core::fmt::Write>::write_str"), None);
+        assert_eq!(crate_name("__wbindgen_malloc"), None);
+    }
+
+    #[test]
+    fn analyze_module() {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        module.extend(section(1, &[1, 0x60, 0, 0]));
+        module.extend(section(3, &[2, 0, 0]));
+        module.extend(section(5, &[1, 0, 1]));
+        module.extend(section(10, &[2, 2, 0, 0x0b, 4, 0, 0x01, 0x01, 0x0b]));
+        module.extend(section(11, &[1, 0, 0x41, 0, 0x0b, 5, 1, 2, 3, 4, 5]));
+        let mut names = vec![4];
+        names.extend_from_slice(b"name");
+        names.extend_from_slice(&[1, 21, 2, 0, 8]);
+        names.extend_from_slice(b"app::run");
+        names.extend_from_slice(&[1, 8]);
+        names.extend_from_slice(b"std::foo");
+        module.extend(section(0, &names));
+
+        let report = analyze(&module).unwrap();
+        assert_eq!(report.total, module.len() as u64);
+        assert_eq!(report.data, 5);
+        assert_eq!(report.sections[0].name, "custom:name");
+        assert_eq!(
+            report.sections.iter().filter(|s| s.name == "code").count(),
+            1
+        );
+        assert_eq!(report.functions[0].name, "std::foo");
+        assert_eq!(report.functions[0].size, 4);
+        assert_eq!(report.functions[1].name, "app::run");
+        let crates: Vec<_> = report.crates.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(crates, vec!["std", "app"]);
+    }
+}
@@ -0,0 +1,125 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use wasmparser::{BinaryReaderError, Parser, Payload};
+
+/// Name of the custom section linking a stripped module to its debug file
+pub const EXTERNAL_DEBUG_INFO: &str = "external_debug_info";
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    ReadWasmError(io::Error),
+    ParseWasmError(BinaryReaderError),
+    WriteWasmError(io::Error),
+}
+
+/// Path of the debug file written next to a stripped module, `<target>.debug.wasm`
+pub fn debug_path(wasm_path: &Path, target_name: &str) -> PathBuf {
+    wasm_path.with_file_name(format!("{}.debug.wasm", target_name))
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    write_leb128(out, contents.len());
+    out.extend_from_slice(contents);
+}
+
+/// Removes the custom sections of a module, such as `name`, `producers` and DWARF sections,
+/// optionally linking it to the url of a debug file with an `external_debug_info` section
+pub fn strip(module: &[u8], debug_url: Option<&str>) -> Result<Vec<u8>, Error> {
+    let mut stripped = Vec::with_capacity(module.len());
+    for payload in Parser::new(0).parse_all(module) {
+        let payload = payload.map_err(Error::ParseWasmError)?;
+        match payload {
+            Payload::Version { range, .. } => stripped.extend_from_slice(&module[range]),
+            Payload::CustomSection(_) => {}
+            _ => {
+                if let Some((id, range)) = payload.as_section() {
+                    write_section(&mut stripped, id, &module[range]);
+                }
+            }
+        }
+    }
+
+    if let Some(debug_url) = debug_url {
+        let mut contents = Vec::new();
+        write_leb128(&mut contents, EXTERNAL_DEBUG_INFO.len());
+        contents.extend_from_slice(EXTERNAL_DEBUG_INFO.as_bytes());
+        write_leb128(&mut contents, debug_url.len());
+        contents.extend_from_slice(debug_url.as_bytes());
+        write_section(&mut stripped, 0, &contents);
+    }
+    Ok(stripped)
+}
+
+/// Strips a module in place
+pub fn strip_file(wasm_path: &Path) -> Result<(), Error> {
+    let module = fs::read(wasm_path).map_err(Error::ReadWasmError)?;
+    let stripped = strip(&module, None)?;
+    fs::write(wasm_path, stripped).map_err(Error::WriteWasmError)
+}
+
+/// Strips a module in place, writing the original module to `<target>.debug.wasm` next to it.
+/// The debug file keeps the code, so its DWARF addresses still apply to the stripped module.
+pub fn split_debug_info(wasm_path: &Path, target_name: &str) -> Result<PathBuf, Error> {
+    let module = fs::read(wasm_path).map_err(Error::ReadWasmError)?;
+    let debug_path = debug_path(wasm_path, target_name);
+    // Resolved relative to the url of the module
+    let debug_url = format!("{}.debug.wasm", target_name);
+    let stripped = strip(&module, Some(&debug_url))?;
+    fs::write(&debug_path, &module).map_err(Error::WriteWasmError)?;
+    fs::write(wasm_path, stripped).map_err(Error::WriteWasmError)?;
+    Ok(debug_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_wasm::{custom_section, section};
+    use validate;
+
+    // A module exporting `main`, with custom sections before and after its code
+    fn module() -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        module.extend(custom_section("producers", &[0]));
+        module.extend(section(1, &[1, 0x60, 0, 0]));
+        module.extend(section(3, &[1, 0]));
+        module.extend(section(7, &[1, 4, b'm', b'a', b'i', b'n', 0, 0]));
+        module.extend(section(10, &[1, 2, 0, 0x0b]));
+        module.extend(custom_section(".debug_info", &[1, 2, 3, 4]));
+        module
+    }
+
+    #[test]
+    fn strip_custom_sections() {
+        let module = module();
+        let mut expected = b"\0asm\x01\0\0\0".to_vec();
+        expected.extend(section(1, &[1, 0x60, 0, 0]));
+        expected.extend(section(3, &[1, 0]));
+        expected.extend(section(7, &[1, 4, b'm', b'a', b'i', b'n', 0, 0]));
+        expected.extend(section(10, &[1, 2, 0, 0x0b]));
+        assert_eq!(strip(&module, None).unwrap(), expected);
+
+        let stripped = strip(&module, Some("app.debug.wasm")).unwrap();
+        let mut data = vec![14];
+        data.extend_from_slice(b"app.debug.wasm");
+        expected.extend(custom_section(EXTERNAL_DEBUG_INFO, &data));
+        assert_eq!(stripped, expected);
+        let interface = validate::validate(&stripped).unwrap();
+        assert_eq!(interface.exported_functions(), vec!["main"]);
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+use addr2line;
+use gimli;
+use gimli::{EndianRcSlice, LittleEndian, SectionId};
+use rustc_demangle::demangle;
+use strip;
+use wasmparser::{
+    BinaryReader, BinaryReaderError, KnownCustom, Name, NameSectionReader, Parser, Payload, TypeRef,
+};
+
+/// Path of the dev server endpoint symbolicating the stack trace of a target, posted as
+/// `{"target": "...", "trace": "..."}`
+pub const ENDPOINT: &str = "/__wasm-bin/symbolicate";
+
+type Reader = EndianRcSlice<LittleEndian>;
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    ReadWasmError(io::Error),
+    ParseWasmError(BinaryReaderError),
+    ReadDwarfError(gimli::Error),
+}
+
+/// A resolved stack frame
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    /// Demangled function name, without the hash
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl Frame {
+    fn describe(&self) -> String {
+        let mut description = self
+            .function
+            .clone()
+            .unwrap_or_else(|| "<unknown>".to_string());
+        if let Some(ref file) = self.file {
+            description.push_str(&format!(" at {}", file));
+            if let Some(line) = self.line {
+                description.push_str(&format!(":{}", line));
+                if let Some(column) = self.column {
+                    description.push_str(&format!(":{}", column));
+                }
+            }
+        }
+        description
+    }
+}
+
+/// Function names and debug info of a wasm module, used to resolve the `wasm-function[N]:0xOFFSET`
+/// frames of browser stack traces
+pub struct Symbols {
+    /// Demangled names from the `name` section, by function index
+    names: HashMap<u32, String>,
+    /// Function index and byte range in the module of each function body
+    bodies: Vec<(u32, Range<usize>)>,
+    /// Offset of the code section contents, which DWARF addresses are relative to
+    code_offset: usize,
+    dwarf: Option<addr2line::Context<Reader>>,
+    /// Url of the debug file of a stripped module
+    external_debug_info: Option<String>,
+}
+
+fn demangled(name: &str) -> String {
+    format!("{:#}", demangle(name))
+}
+
+/// Demangled function names of a `name` section, without hashes, by function index
+pub fn function_names(
+    reader: NameSectionReader,
+) -> Result<HashMap<u32, String>, BinaryReaderError> {
+    let mut names = HashMap::new();
+    for name in reader {
+        if let Name::Function(map) = name? {
+            for naming in map {
+                let naming = naming?;
+                names.insert(naming.index, demangled(naming.name));
+            }
+        }
+    }
+    Ok(names)
+}
+
+impl Symbols {
+    /// Loads the symbols of a module, reading names and debug info from the debug file next to
+    /// it if the module was stripped
+    pub fn load(path: &Path) -> Result<Symbols, Error> {
+        let module = fs::read(path).map_err(Error::ReadWasmError)?;
+        let mut symbols = Symbols::parse(&module)?;
+        let debug_path = symbols
+            .external_debug_info
+            .as_ref()
+            .filter(|url| !url.contains(['/', '\\', ':']))
+            .map(|url| path.with_file_name(url));
+        if let Some(debug_path) = debug_path {
+            if debug_path.is_file() {
+                let debug = fs::read(&debug_path).map_err(Error::ReadWasmError)?;
+                let debug = Symbols::parse(&debug)?;
+                // The stripped module has the same code, so DWARF addresses, which are relative to
+                // the code section, still apply to it
+                symbols.names = debug.names;
+                symbols.dwarf = debug.dwarf;
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn parse(module: &[u8]) -> Result<Symbols, Error> {
+        let mut names = HashMap::new();
+        let mut bodies = Vec::new();
+        let mut code_offset = 0;
+        let mut imported_functions = 0;
+        let mut debug_sections = HashMap::new();
+        let mut external_debug_info = None;
+        for payload in Parser::new(0).parse_all(module) {
+            match payload.map_err(Error::ParseWasmError)? {
+                Payload::ImportSection(imports) => {
+                    for import in imports {
+                        if let TypeRef::Func(_) = import.map_err(Error::ParseWasmError)?.ty {
+                            imported_functions += 1;
+                        }
+                    }
+                }
+                Payload::CodeSectionStart { range, .. } => code_offset = range.start,
+                Payload::CodeSectionEntry(body) => {
+                    let index = imported_functions + bodies.len() as u32;
+                    bodies.push((index, body.range()));
+                }
+                Payload::CustomSection(section) => match section.as_known() {
+                    KnownCustom::Name(reader) => {
+                        names = function_names(reader).map_err(Error::ParseWasmError)?;
+                    }
+                    _ if section.name() == strip::EXTERNAL_DEBUG_INFO => {
+                        let mut reader = BinaryReader::new(section.data(), section.data_offset());
+                        let url = reader.read_string().map_err(Error::ParseWasmError)?;
+                        external_debug_info = Some(url.to_string());
+                    }
+                    _ if section.name().starts_with(".debug_") => {
+                        debug_sections.insert(section.name().to_string(), section.data().to_vec());
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let dwarf = if debug_sections.contains_key(".debug_info") {
+            let dwarf = gimli::Dwarf::load(|id: SectionId| -> Result<Reader, gimli::Error> {
+                let data = debug_sections.remove(id.name()).unwrap_or_default();
+                Ok(EndianRcSlice::new(Rc::from(data), LittleEndian))
+            })
+            .map_err(Error::ReadDwarfError)?;
+            Some(addr2line::Context::from_dwarf(dwarf).map_err(Error::ReadDwarfError)?)
+        } else {
+            None
+        };
+
+        Ok(Symbols {
+            names,
+            bodies,
+            code_offset,
+            dwarf,
+            external_debug_info,
+        })
+    }
+
+    /// Resolves a frame from its function index and/or its byte offset in the module. With
+    /// debug info the frames of inlined functions are included, innermost first.
+    pub fn lookup(&self, function: Option<u32>, offset: Option<u64>) -> Vec<Frame> {
+        let function = function.or_else(|| {
+            let offset = offset? as usize;
+            self.bodies
+                .iter()
+                .find(|(_, range)| range.contains(&offset))
+                .map(|&(index, _)| index)
+        });
+        let name = function.and_then(|index| self.names.get(&index).cloned());
+
+        let mut frames = Vec::new();
+        if let (Some(dwarf), Some(offset)) = (self.dwarf.as_ref(), offset) {
+            let address = offset.saturating_sub(self.code_offset as u64);
+            if let Ok(mut iter) = dwarf.find_frames(address).skip_all_loads() {
+                while let Ok(Some(frame)) = iter.next() {
+                    let function = frame
+                        .function
+                        .as_ref()
+                        .and_then(|function| function.raw_name().ok())
+                        .map(|raw_name| demangled(raw_name.as_ref()));
+                    let location = frame.location.as_ref();
+                    frames.push(Frame {
+                        function,
+                        file: location.and_then(|l| l.file.map(String::from)),
+                        line: location.and_then(|l| l.line),
+                        column: location.and_then(|l| l.column),
+                    });
+                }
+            }
+        }
+        match frames.last_mut() {
+            Some(outermost) if outermost.function.is_none() => outermost.function = name,
+            Some(_) => {}
+            None if name.is_some() => frames.push(Frame {
+                function: name,
+                file: None,
+                line: None,
+                column: None,
+            }),
+            None => {}
+        }
+        frames
+    }
+
+    /// Appends the resolved frames to every line of a stack trace that refers to a
+    /// `wasm-function[N]`, optionally followed by `:0xOFFSET`
+    pub fn symbolicate(&self, trace: &str) -> String {
+        trace
+            .lines()
+            .map(|line| match wasm_frame(line) {
+                Some((function, offset)) => {
+                    let frames = self.lookup(Some(function), offset);
+                    if frames.is_empty() {
+                        return line.to_string();
+                    }
+                    let description = frames
+                        .iter()
+                        .map(Frame::describe)
+                        .collect::<Vec<_>>()
+                        .join(", inlined into ");
+                    format!("{} => {}", line, description)
+                }
+                None => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// Parses the last `wasm-function[N]` of a stack trace line and the `:0xOFFSET` following it
+fn wasm_frame(line: &str) -> Option<(u32, Option<u64>)> {
+    const MARKER: &str = "wasm-function[";
+    let start = line.rfind(MARKER)? + MARKER.len();
+    let end = start + line[start..].find(']')?;
+    let function = line[start..end].parse().ok()?;
+    let rest = &line[end + 1..];
+    let offset = rest.strip_prefix(":0x").and_then(|hex| {
+        let hex: String = hex.chars().take_while(|c| c.is_ascii_hexdigit()).collect();
+        u64::from_str_radix(&hex, 16).ok()
+    });
+    Some((function, offset))
+}
+
+/// Whether a text contains wasm stack frames that can be symbolicated
+pub fn has_wasm_frames(text: &str) -> bool {
+    text.lines().any(|line| wasm_frame(line).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use test_wasm::named_module;
+
+    #[test]
+    fn load_names_of_stripped_module() {
+        let (module, body_offset) = named_module("_ZN3app4main17h0123456789abcdefE");
+        let dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/symbolicate-stripped");
+        fs::create_dir_all(&dir).unwrap();
+        let wasm_path = dir.join("app_bg.wasm");
+        fs::write(&wasm_path, &module).unwrap();
+        strip::split_debug_info(&wasm_path, "app").unwrap();
+
+        let stripped = fs::read(&wasm_path).unwrap();
+        assert_eq!(
+            Symbols::parse(&stripped).unwrap().lookup(Some(1), None),
+            vec![]
+        );
+        let symbols = Symbols::load(&wasm_path).unwrap();
+        assert_eq!(
+            symbols.lookup(None, Some(body_offset + 1))[0].function,
+            Some("app::main".to_string())
+        );
+    }
+}
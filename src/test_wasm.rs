@@ -0,0 +1,66 @@
+// Wasm modules written by hand for tests. Also included by the integration tests' utils.
+// Each test only uses some of these helpers
+#![allow(dead_code)]
+
+fn write_leb128(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// A wasm section, its size encoded as LEB128
+pub fn section(id: u8, payload: &[u8]) -> Vec<u8> {
+    let mut section = vec![id];
+    write_leb128(&mut section, payload.len());
+    section.extend_from_slice(payload);
+    section
+}
+
+/// A wasm custom section with a name
+pub fn custom_section(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    write_leb128(&mut payload, name.len());
+    payload.extend_from_slice(name.as_bytes());
+    payload.extend_from_slice(data);
+    section(0, &payload)
+}
+
+/// A wasm module exporting an empty `main` function
+pub fn main_module() -> Vec<u8> {
+    let mut module = b"\0asm\x01\0\0\0".to_vec();
+    module.extend(section(1, &[1, 0x60, 0, 0]));
+    module.extend(section(3, &[1, 0]));
+    module.extend(section(7, &[1, 4, b'm', b'a', b'i', b'n', 0, 0]));
+    module.extend(section(10, &[1, 2, 0, 0x0b]));
+    module
+}
+
+/// A module importing one function and defining one, named by the name section. Returns the
+/// module and the offset of the defined function's body.
+pub fn named_module(mangled_name: &str) -> (Vec<u8>, u64) {
+    let mut module = b"\0asm\x01\0\0\0".to_vec();
+    module.extend(section(1, &[1, 0x60, 0, 0]));
+    module.extend(section(2, &[1, 3, b'e', b'n', b'v', 1, b'f', 0, 0]));
+    module.extend(section(3, &[1, 0]));
+    let body_offset = module.len() as u64 + 4;
+    module.extend(section(10, &[1, 2, 0, 0x0b]));
+
+    let mut names = vec![4];
+    names.extend_from_slice(b"name");
+    names.extend_from_slice(&[
+        1,
+        mangled_name.len() as u8 + 3,
+        1,
+        1,
+        mangled_name.len() as u8,
+    ]);
+    names.extend_from_slice(mangled_name.as_bytes());
+    module.extend(section(0, &names));
+    (module, body_offset)
+}
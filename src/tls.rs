@@ -0,0 +1,246 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::net::IpAddr;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use futures::{Async, Future, Poll};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::ssl::{HandshakeError, SslAcceptor, SslFiletype, SslMethod, SslStream};
+use openssl::x509::extension::{BasicConstraints, ExtendedKeyUsage, SubjectAlternativeName};
+use openssl::x509::{X509NameBuilder, X509};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+/// Directory the self-signed certificate is cached in when the server isn't given one
+pub const CERT_DIR: &str = "./target/wasm-bin/certs";
+const CERT_FILE: &str = "localhost.pem";
+const KEY_FILE: &str = "localhost.key";
+const CERT_VALID_DAYS: u32 = 365;
+// A cached certificate expiring sooner than this is replaced
+const CERT_RENEW_DAYS: u32 = 1;
+// Names the self-signed certificate is valid for
+const DNS_NAMES: &[&str] = &["localhost"];
+const IP_ADDRESSES: &[&str] = &["127.0.0.1", "::1"];
+
+#[derive(Debug)]
+pub enum Error {
+    CreateCertDirectoryError(io::Error),
+    WriteCertError(io::Error),
+    ReadCertError(io::Error),
+    GenerateCertError(ErrorStack),
+    LoadCertError(ErrorStack),
+}
+
+/// A certificate and private key pair in PEM format
+pub struct Identity {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Returns the self-signed certificate for localhost cached in a directory, generating it if it
+/// doesn't exist, is about to expire or was made for other names
+pub fn self_signed_identity(cert_dir: &Path) -> Result<Identity, Error> {
+    let identity = Identity {
+        cert: cert_dir.join(CERT_FILE),
+        key: cert_dir.join(KEY_FILE),
+    };
+    if identity.key.exists() && is_current(&identity.cert) {
+        return Ok(identity);
+    }
+
+    println!("wasm-bin: Generate self-signed certificate for localhost");
+    let (cert, key) = generate_self_signed().map_err(Error::GenerateCertError)?;
+    fs::create_dir_all(cert_dir).map_err(Error::CreateCertDirectoryError)?;
+    write_key(&identity.key, &key).map_err(Error::WriteCertError)?;
+    fs::write(&identity.cert, cert).map_err(Error::WriteCertError)?;
+    Ok(identity)
+}
+
+// Whether a cached certificate is still valid for a while and covers the expected names
+fn is_current(cert_path: &Path) -> bool {
+    let cert = match fs::read(cert_path).map(|pem| X509::from_pem(&pem)) {
+        Ok(Ok(cert)) => cert,
+        _ => return false,
+    };
+    let renew = match Asn1Time::days_from_now(CERT_RENEW_DAYS) {
+        Ok(renew) => renew,
+        Err(_) => return false,
+    };
+    if cert.not_after() < renew {
+        return false;
+    }
+
+    let (mut dns_names, mut ip_addresses) = (Vec::new(), Vec::new());
+    for name in cert.subject_alt_names().iter().flatten() {
+        if let Some(dns_name) = name.dnsname() {
+            dns_names.push(dns_name.to_string());
+        } else if let Some(ip_address) = name.ipaddress() {
+            ip_addresses.push(ip_address.to_vec());
+        }
+    }
+    let expected_ips: Vec<Vec<u8>> = IP_ADDRESSES
+        .iter()
+        .map(|ip| match ip.parse::<IpAddr>().unwrap() {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        })
+        .collect();
+    dns_names == DNS_NAMES && ip_addresses == expected_ips
+}
+
+// Writes a private key readable only by its owner
+fn write_key(path: &Path, key: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    // The mode only applies to new files
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(key)
+}
+
+// Generates a PEM encoded certificate and private key valid for the loopback addresses
+fn generate_self_signed() -> Result<(Vec<u8>, Vec<u8>), ErrorStack> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", "wasm-bin localhost")?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    let serial = serial.to_asn1_integer()?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(CERT_VALID_DAYS)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&key)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+    builder.append_extension(BasicConstraints::new().build()?)?;
+    builder.append_extension(ExtendedKeyUsage::new().server_auth().build()?)?;
+    let mut subject_alt_names = SubjectAlternativeName::new();
+    for dns_name in DNS_NAMES {
+        subject_alt_names.dns(dns_name);
+    }
+    for ip_address in IP_ADDRESSES {
+        subject_alt_names.ip(ip_address);
+    }
+    let subject_alt_names = subject_alt_names.build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(subject_alt_names)?;
+    builder.sign(&key, MessageDigest::sha256())?;
+
+    Ok((builder.build().to_pem()?, key.private_key_to_pem_pkcs8()?))
+}
+
+/// SHA-256 fingerprint of a PEM certificate, as colon separated hex
+pub fn fingerprint(cert_path: &Path) -> Result<String, Error> {
+    let pem = fs::read(cert_path).map_err(Error::ReadCertError)?;
+    let cert = X509::from_pem(&pem).map_err(Error::LoadCertError)?;
+    let digest = cert
+        .digest(MessageDigest::sha256())
+        .map_err(Error::LoadCertError)?;
+    let hex: Vec<String> = digest.iter().map(|b| format!("{:02X}", b)).collect();
+    Ok(hex.join(":"))
+}
+
+pub fn acceptor(identity: &Identity) -> Result<SslAcceptor, Error> {
+    let mut builder =
+        SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).map_err(Error::LoadCertError)?;
+    builder
+        .set_certificate_chain_file(&identity.cert)
+        .map_err(Error::LoadCertError)?;
+    builder
+        .set_private_key_file(&identity.key, SslFiletype::PEM)
+        .map_err(Error::LoadCertError)?;
+    builder.check_private_key().map_err(Error::LoadCertError)?;
+    Ok(builder.build())
+}
+
+/// Performs the server side of the TLS handshake on a non-blocking stream
+pub fn accept<S: Read + Write>(acceptor: &SslAcceptor, stream: S) -> Accept<S> {
+    Accept(Some(acceptor.accept(stream)))
+}
+
+pub struct Accept<S>(Option<Result<SslStream<S>, HandshakeError<S>>>);
+
+impl<S: Read + Write> Future for Accept<S> {
+    type Item = TlsStream<S>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<TlsStream<S>, io::Error> {
+        let mid_handshake = match self.0.take().expect("polled after completion") {
+            Ok(stream) => return Ok(Async::Ready(TlsStream(stream))),
+            Err(HandshakeError::WouldBlock(mid_handshake)) => mid_handshake,
+            Err(e) => return Err(handshake_error(e)),
+        };
+        match mid_handshake.handshake() {
+            Ok(stream) => Ok(Async::Ready(TlsStream(stream))),
+            Err(HandshakeError::WouldBlock(mid_handshake)) => {
+                self.0 = Some(Err(HandshakeError::WouldBlock(mid_handshake)));
+                Ok(Async::NotReady)
+            }
+            Err(e) => Err(handshake_error(e)),
+        }
+    }
+}
+
+fn handshake_error<S>(e: HandshakeError<S>) -> io::Error {
+    let message = match e {
+        HandshakeError::SetupFailure(e) => e.to_string(),
+        HandshakeError::Failure(mid_handshake) | HandshakeError::WouldBlock(mid_handshake) => {
+            mid_handshake.error().to_string()
+        }
+    };
+    io::Error::other(message)
+}
+
+/// An established TLS connection usable as hyper transport
+pub struct TlsStream<S>(SslStream<S>);
+
+impl<S: Read + Write> Read for TlsStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<S: Read + Write> Write for TlsStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> AsyncRead for TlsStream<S> {}
+
+impl<S: AsyncRead + AsyncWrite> AsyncWrite for TlsStream<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self.0.shutdown() {
+            Ok(_) => {}
+            Err(ref e) if e.io_error().map(|e| e.kind()) == Some(io::ErrorKind::WouldBlock) => {
+                return Ok(Async::NotReady);
+            }
+            // The peer may already have closed the connection
+            Err(_) => {}
+        }
+        self.0.get_mut().shutdown()
+    }
+}
@@ -1,28 +1,67 @@
+use std::fs;
 use std::io;
+use std::path::Path;
 
 #[derive(Debug)]
 pub enum Error {
     ReadLineError(io::Error),
 }
 
+/// Asks a yes/no question on stdin, answering no once stdin is closed
 pub fn prompt_confirm(text: &str) -> Result<bool, Error> {
     println!("{}", text);
 
     let read_in = || {
         let mut buf = String::new();
-        io::stdin()
+        let read = io::stdin()
             .read_line(&mut buf)
             .map_err(Error::ReadLineError)?;
-        Ok(match buf.trim_right() {
+        if read == 0 {
+            println!("wasm-bin: No answer before the end of input, assuming no");
+            return Ok(Some(false));
+        }
+        Ok(match buf.trim_end() {
             "y" | "Y" => Some(true),
             "n" | "N" => Some(false),
             _ => None,
         })
     };
     loop {
-        match read_in()? {
-            Some(v) => return Ok(v),
-            _ => {}
+        if let Some(v) = read_in()? {
+            return Ok(v);
+        }
+    }
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+pub fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// Copies the contents of a directory into another one, recursively
+pub fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
         }
     }
+    Ok(())
 }
@@ -0,0 +1,207 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use wasmparser::{BinaryReaderError, ExternalKind, Parser, Payload, TypeRef, Validator};
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    ReadWasmError(io::Error),
+    InvalidModuleError(BinaryReaderError),
+    /// The entry function is not exported, with the functions that are
+    MissingEntryError(String, Vec<String>),
+}
+
+/// Kind of an imported or exported item
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Function,
+    Table,
+    Memory,
+    Global,
+    Tag,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Kind::Function => "function",
+            Kind::Table => "table",
+            Kind::Memory => "memory",
+            Kind::Global => "global",
+            Kind::Tag => "tag",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Export {
+    pub name: String,
+    pub kind: Kind,
+}
+
+#[derive(Debug)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub kind: Kind,
+}
+
+/// Exports and imports of a wasm module
+#[derive(Debug)]
+pub struct Interface {
+    pub exports: Vec<Export>,
+    pub imports: Vec<Import>,
+}
+
+impl Interface {
+    pub fn exported_functions(&self) -> Vec<&str> {
+        self.exports
+            .iter()
+            .filter(|export| export.kind == Kind::Function)
+            .map(|export| export.name.as_str())
+            .collect()
+    }
+
+    /// Checks that the entry function called by the html loader is exported
+    pub fn check_entry(&self, entry: &str) -> Result<(), Error> {
+        let functions = self.exported_functions();
+        if functions.contains(&entry) {
+            Ok(())
+        } else {
+            Err(Error::MissingEntryError(
+                entry.to_string(),
+                functions.into_iter().map(String::from).collect(),
+            ))
+        }
+    }
+}
+
+fn import_kind(ty: TypeRef) -> Kind {
+    match ty {
+        TypeRef::Func(_) => Kind::Function,
+        TypeRef::Table(_) => Kind::Table,
+        TypeRef::Memory(_) => Kind::Memory,
+        TypeRef::Global(_) => Kind::Global,
+        TypeRef::Tag(_) => Kind::Tag,
+    }
+}
+
+fn export_kind(kind: ExternalKind) -> Kind {
+    match kind {
+        ExternalKind::Func => Kind::Function,
+        ExternalKind::Table => Kind::Table,
+        ExternalKind::Memory => Kind::Memory,
+        ExternalKind::Global => Kind::Global,
+        ExternalKind::Tag => Kind::Tag,
+    }
+}
+
+/// Validates a wasm module and reads its interface
+pub fn validate_file(path: &Path) -> Result<Interface, Error> {
+    let module = fs::read(path).map_err(Error::ReadWasmError)?;
+    validate(&module)
+}
+
+pub fn validate(module: &[u8]) -> Result<Interface, Error> {
+    Validator::new()
+        .validate_all(module)
+        .map_err(Error::InvalidModuleError)?;
+
+    let mut interface = Interface {
+        exports: Vec::new(),
+        imports: Vec::new(),
+    };
+    for payload in Parser::new(0).parse_all(module) {
+        match payload.map_err(Error::InvalidModuleError)? {
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    let import = import.map_err(Error::InvalidModuleError)?;
+                    interface.imports.push(Import {
+                        module: import.module.to_string(),
+                        name: import.name.to_string(),
+                        kind: import_kind(import.ty),
+                    });
+                }
+            }
+            Payload::ExportSection(exports) => {
+                for export in exports {
+                    let export = export.map_err(Error::InvalidModuleError)?;
+                    interface.exports.push(Export {
+                        name: export.name.to_string(),
+                        kind: export_kind(export.kind),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(interface)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_wasm::{custom_section, section};
+
+    // A module importing `env.log` and exporting a `main` function and its memory
+    fn module() -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        module.extend(section(1, &[1, 0x60, 0, 0]));
+        module.extend(section(
+            2,
+            &[1, 3, b'e', b'n', b'v', 3, b'l', b'o', b'g', 0, 0],
+        ));
+        module.extend(section(3, &[1, 0]));
+        module.extend(section(5, &[1, 0, 1]));
+        module.extend(section(
+            7,
+            &[
+                2, 4, b'm', b'a', b'i', b'n', 0, 1, 6, b'm', b'e', b'm', b'o', b'r', b'y', 2, 0,
+            ],
+        ));
+        module.extend(section(10, &[1, 4, 0, 0x10, 0, 0x0b]));
+        module
+    }
+
+    #[test]
+    fn read_interface() {
+        let interface = validate(&module()).unwrap();
+        assert_eq!(interface.imports.len(), 1);
+        assert_eq!(interface.imports[0].module, "env");
+        assert_eq!(interface.imports[0].name, "log");
+        assert_eq!(interface.imports[0].kind, Kind::Function);
+        assert_eq!(interface.exported_functions(), vec!["main"]);
+        assert_eq!(interface.exports[1].kind, Kind::Memory);
+        assert!(interface.check_entry("main").is_ok());
+        match interface.check_entry("start") {
+            Err(Error::MissingEntryError(entry, exported)) => {
+                assert_eq!(entry, "start");
+                assert_eq!(exported, vec!["main".to_string()]);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_large_sections() {
+        // Sizes of 128 bytes and more take several bytes to encode
+        let mut module = module();
+        module.extend(custom_section("producers", &[0; 300]));
+        let interface = validate(&module).unwrap();
+        assert_eq!(interface.exported_functions(), vec!["main"]);
+    }
+
+    #[test]
+    fn reject_invalid_module() {
+        let mut module = module();
+        // Call a function index that doesn't exist
+        let len = module.len();
+        module[len - 2] = 5;
+        match validate(&module) {
+            Err(Error::InvalidModuleError(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
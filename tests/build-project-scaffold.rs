@@ -0,0 +1,47 @@
+extern crate wasm_bin;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use wasm_bin::build;
+use wasm_bin::build::{InstallPolicy, PackageType};
+use wasm_bin::{ScaffoldOptions, Template};
+
+// Scaffolds a project in target/tests and builds it the way `wasm-bin build` does
+fn build_scaffolded(name: &str, template: Template) -> Vec<build::TargetPackage> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target/tests")
+        .join(name);
+    let _ = fs::remove_dir_all(&dir);
+    let options = ScaffoldOptions {
+        template,
+        html: true,
+        static_dir: true,
+        ..ScaffoldOptions::default()
+    };
+    wasm_bin::new_project(&dir, &options).unwrap();
+    env::set_current_dir(&dir).unwrap();
+    let build_options = build::Options {
+        install: InstallPolicy::Always,
+        ..build::Options::default()
+    };
+    build::build(&build_options).unwrap()
+}
+
+#[test]
+fn build_scaffolded_projects() {
+    let targets = build_scaffolded("build-project-scaffold-bin", Template::Bin);
+    assert_eq!(targets.len(), 1);
+    assert_eq!(targets[0].name, "build-project-scaffold-bin");
+    assert!(targets[0].path.is_file());
+    assert!(targets[0].wasm_path.is_file());
+    assert!(targets[0].path.with_file_name("style.css").is_file());
+
+    let targets = build_scaffolded("build-project-scaffold-lib", Template::Lib);
+    assert_eq!(targets.len(), 1);
+    assert!(matches!(targets[0].ty, PackageType::Library));
+    assert_eq!(targets[0].name, "build_project_scaffold_lib");
+    assert!(targets[0].path.is_file());
+    assert!(targets[0].wasm_path.is_file());
+}
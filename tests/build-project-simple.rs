@@ -63,13 +63,13 @@ fn build_project_simple() {
             <html>
                 <head>
                     <meta content="text/html;charset=utf-8" http-equiv="Content-Type" />
-                    <script src='./test_project_simple.js'></script>
+                    <script src='{{js}}'></script>
                     <script>
                         function add(l, r) {
                             return l + r;
                         }
                         window.addEventListener('load', function() {
-                            wasm_bindgen("./test_project_simple_bg.wasm").then(function() {
+                            wasm_bindgen("{{wasm}}").then(function() {
                                 wasm_bindgen.wasm.main();
                             });
                         }, false);
@@ -0,0 +1,101 @@
+extern crate wasm_bin;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use wasm_bin::{CleanError, CleanOptions};
+
+fn touch(path: &Path) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, "contents").unwrap();
+}
+
+#[test]
+fn clean_outputs() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/clean");
+    let _ = fs::remove_dir_all(&dir);
+    let manifest = dir.join("Cargo.toml");
+    touch(&dir.join("src/main.rs"));
+    fs::write(
+        &manifest,
+        "[package]\nname = \"app\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+    let manifest_path = Some(manifest.to_string_lossy().into_owned());
+    let outputs = dir.join("target/wasm-bin");
+    touch(&outputs.join("debug/app/app.js"));
+    touch(&outputs.join("debug/other/other.js"));
+    touch(&outputs.join("release/app/app.js"));
+    touch(&outputs.join("cache/compress/0123456789abcdef.br"));
+    touch(&outputs.join("certs/localhost.pem"));
+
+    let mut options = CleanOptions {
+        bin: Some("app".to_string()),
+        manifest_path: manifest_path.clone(),
+        profile_dir: Some("release".to_string()),
+        dry_run: true,
+        ..CleanOptions::default()
+    };
+    let removals = wasm_bin::clean(&options).unwrap();
+    let paths: Vec<_> = removals.iter().map(|r| r.path.clone()).collect();
+    assert_eq!(paths, vec![outputs.join("release/app")]);
+    assert_eq!(removals[0].size, 8);
+    assert!(outputs.join("release/app").exists());
+
+    // Selected targets keep the compression cache, whose entries belong to no target
+    options.dry_run = false;
+    wasm_bin::clean(&options).unwrap();
+    assert!(!outputs.join("release/app").exists());
+    assert!(outputs.join("cache").exists());
+    assert!(outputs.join("debug/app").exists());
+
+    let options = CleanOptions {
+        manifest_path: manifest_path.clone(),
+        ..CleanOptions::default()
+    };
+    wasm_bin::clean(&options).unwrap();
+    assert!(!outputs.join("debug").exists());
+    assert!(!outputs.join("release").exists());
+    assert!(!outputs.join("cache").exists());
+    assert!(outputs.join("certs/localhost.pem").exists());
+
+    // Names that would leave target/wasm-bin are refused before anything is removed
+    touch(&dir.join("target/debug/app.wasm"));
+    for (profile_dir, bin) in &[
+        (Some(".."), None),
+        (Some("."), None),
+        (Some("/"), None),
+        (Some("release/.."), None),
+        (None, Some("../../src")),
+        (None, Some("..")),
+        (Some("debug"), Some("../..")),
+    ] {
+        let options = CleanOptions {
+            profile_dir: profile_dir.map(String::from),
+            bin: bin.map(String::from),
+            manifest_path: manifest_path.clone(),
+            ..CleanOptions::default()
+        };
+        match wasm_bin::clean(&options) {
+            Err(CleanError::InvalidNameError(_)) => {}
+            other => panic!("{:?} {:?}: {:?}", profile_dir, bin, other.map(|_| ())),
+        }
+    }
+
+    // A profile directory linking outside of target/wasm-bin
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink("../../src", outputs.join("linked")).unwrap();
+        let options = CleanOptions {
+            profile_dir: Some("linked".to_string()),
+            manifest_path,
+            ..CleanOptions::default()
+        };
+        match wasm_bin::clean(&options) {
+            Err(CleanError::OutsideOutputsError(_)) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+    assert!(dir.join("target/debug/app.wasm").exists());
+    assert!(dir.join("src/main.rs").exists());
+}
@@ -0,0 +1,171 @@
+extern crate serde_json;
+extern crate wasm_bin;
+
+mod utils;
+
+use wasm_bin::build;
+use wasm_bin::build::{BindgenError, BindgenOutput, CargoError, Diagnostic};
+use wasm_bin::run;
+
+use utils::{get, local};
+
+// Printed by `rustc --error-format=json` for `let v: Vec<u8> = "<b>&</b>";`, without the
+// explanation of the error code
+const MISMATCHED_TYPES: &str = r#"{
+  "message": "mismatched types",
+  "code": {
+    "code": "E0308",
+    "explanation": null
+  },
+  "level": "error",
+  "spans": [
+    {
+      "file_name": "main.rs",
+      "byte_start": 33,
+      "byte_end": 43,
+      "line_start": 2,
+      "line_end": 2,
+      "column_start": 22,
+      "column_end": 32,
+      "is_primary": true,
+      "text": [
+        {
+          "text": "    let v: Vec<u8> = \"<b>&</b>\";",
+          "highlight_start": 22,
+          "highlight_end": 32
+        }
+      ],
+      "label": "expected `Vec<u8>`, found `&str`",
+      "suggested_replacement": null,
+      "suggestion_applicability": null,
+      "expansion": null
+    },
+    {
+      "file_name": "main.rs",
+      "byte_start": 23,
+      "byte_end": 30,
+      "line_start": 2,
+      "line_end": 2,
+      "column_start": 12,
+      "column_end": 19,
+      "is_primary": false,
+      "text": [
+        {
+          "text": "    let v: Vec<u8> = \"<b>&</b>\";",
+          "highlight_start": 12,
+          "highlight_end": 19
+        }
+      ],
+      "label": "expected due to this",
+      "suggested_replacement": null,
+      "suggestion_applicability": null,
+      "expansion": null
+    }
+  ],
+  "children": [
+    {
+      "message": "expected struct `Vec<u8>`\nfound reference `&'static str`",
+      "code": null,
+      "level": "note",
+      "spans": [],
+      "children": [],
+      "rendered": null
+    },
+    {
+      "message": "call `Into::into` on this expression to convert `&'static str` into `Vec<u8>`",
+      "code": null,
+      "level": "help",
+      "spans": [
+        {
+          "file_name": "main.rs",
+          "byte_start": 43,
+          "byte_end": 43,
+          "line_start": 2,
+          "line_end": 2,
+          "column_start": 32,
+          "column_end": 32,
+          "is_primary": true,
+          "text": [
+            {
+              "text": "    let v: Vec<u8> = \"<b>&</b>\";",
+              "highlight_start": 32,
+              "highlight_end": 32
+            }
+          ],
+          "label": null,
+          "suggested_replacement": ".into()",
+          "suggestion_applicability": "MaybeIncorrect",
+          "expansion": null
+        }
+      ],
+      "children": [],
+      "rendered": null
+    }
+  ],
+  "rendered": "error[E0308]: mismatched types\n --> main.rs:2:22\n  |\n2 |     let v: Vec<u8> = \"<b>&</b>\";\n  |            -------   ^^^^^^^^^^ expected `Vec<u8>`, found `&str`\n  |            |\n  |            expected due to this\n  |\n  = note: expected struct `Vec<u8>`\n          found reference `&'static str`\nhelp: call `Into::into` on this expression to convert `&'static str` into `Vec<u8>`\n  |\n2 |     let v: Vec<u8> = \"<b>&</b>\".into();\n  |                                +++++++\n\n"
+}"#;
+
+fn serve_error(error: &build::Error) -> run::Server {
+    wasm_bin::serve_build_error(error, &local(run::Options::default())).unwrap()
+}
+
+fn build_error_page(error: &build::Error) -> String {
+    let server = serve_error(error);
+    let page = get(server.addr(), "/", "").text();
+    server.shutdown().unwrap();
+    page
+}
+
+#[test]
+fn render_compile_errors() {
+    let diagnostic: Diagnostic = serde_json::from_str(MISMATCHED_TYPES).unwrap();
+    let error = build::Error::CargoBuildError(CargoError::CompileErrors(vec![diagnostic]));
+    let page = build_error_page(&error);
+
+    assert!(page.contains("<h2>error[E0308]: mismatched types</h2>"));
+    // Both spans, with their location, highlighted source and label
+    assert!(page.contains("<p class=\"location\">main.rs:2:22</p>"));
+    assert!(page.contains("<p class=\"location\">main.rs:2:12</p>"));
+    assert!(page.contains(
+        "<span class=\"line-number\">2 |</span>     let v: Vec&lt;u8&gt; = \
+         <mark>&quot;&lt;b&gt;&amp;&lt;/b&gt;&quot;</mark>;"
+    ));
+    assert!(page.contains(
+        "<span class=\"line-number\">2 |</span>     let v: <mark>Vec&lt;u8&gt;</mark> = "
+    ));
+    assert!(page.contains("<p>expected `Vec&lt;u8&gt;`, found `&amp;str`</p>"));
+    assert!(page.contains("<p>expected due to this</p>"));
+    // Notes and help of the error
+    assert!(page.contains("<p><b>note</b>: expected struct `Vec&lt;u8&gt;`"));
+    assert!(page.contains("<p><b>help</b>: call `Into::into` on this expression"));
+    // Nothing from the source is left unescaped
+    assert!(!page.contains("<b>&"));
+}
+
+#[test]
+fn serve_build_error_at_every_route() {
+    let error = build::Error::BindgenError(BindgenError::BindgenFailed(BindgenOutput {
+        stdout: String::new(),
+        stderr: "error: <input> is not valid & can't be read\n".to_string(),
+    }));
+    let server = serve_error(&error);
+    for path in &["/", "/app/", "/app/app.js", "/missing.wasm"] {
+        let response = get(server.addr(), path, "");
+        assert_eq!(response.status(), 500, "{}: {}", path, response.head);
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+        let page = response.text();
+        assert!(page.contains("<title>Build failed</title>"));
+        assert!(page.contains("<p>wasm-bindgen failed</p>"));
+        assert!(page.contains("error: &lt;input&gt; is not valid &amp; can&#39;t be read"));
+    }
+    server.shutdown().unwrap();
+
+    // Other errors are described by their debug representation
+    let error = build::Error::CargoBuildError(CargoError::UnexpectedFileCountError);
+    assert!(
+        build_error_page(&error).contains("<pre>CargoBuildError(UnexpectedFileCountError)</pre>")
+    );
+}
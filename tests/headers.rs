@@ -0,0 +1,76 @@
+extern crate wasm_bin;
+
+mod utils;
+
+use std::path::PathBuf;
+
+use wasm_bin::run;
+use wasm_bin::run::HeaderRule;
+
+use utils::{get, serve_app};
+
+#[test]
+fn parse_header_rules() {
+    let rule: HeaderRule = "X-Frame-Options: DENY".parse().unwrap();
+    assert!(rule.pattern.matches("/any/path"));
+    assert_eq!(rule.name, "X-Frame-Options");
+    assert_eq!(rule.value, "DENY");
+
+    let rule: HeaderRule = " /*.wasm = Cache-Control : max-age=60 ".parse().unwrap();
+    assert!(rule.pattern.matches("/app_bg.wasm"));
+    assert!(!rule.pattern.matches("/app.js"));
+    assert_eq!(rule.name, "Cache-Control");
+    assert_eq!(rule.value, "max-age=60");
+
+    // Only a `=` before the colon separates a pattern, the value may contain both
+    let rule: HeaderRule = "Link: <http://localhost/a=b>; rel=preload".parse().unwrap();
+    assert!(rule.pattern.matches("/"));
+    assert_eq!(rule.name, "Link");
+    assert_eq!(rule.value, "<http://localhost/a=b>; rel=preload");
+
+    let rule: HeaderRule = "X-Empty:".parse().unwrap();
+    assert_eq!(rule.value, "");
+}
+
+#[test]
+fn reject_malformed_header_rules() {
+    for spec in &[
+        "X-Frame-Options DENY",
+        ": DENY",
+        "/*.js=: DENY",
+        "X Frame: DENY",
+        "X-Frame\u{e9}: DENY",
+        "[=X-Frame-Options: DENY",
+    ] {
+        assert!(spec.parse::<HeaderRule>().is_err(), "{}", spec);
+    }
+}
+
+#[test]
+fn later_header_rules_take_precedence() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/headers");
+    let options = run::Options {
+        cross_origin_isolated: true,
+        headers: [
+            "X-Test: one",
+            "/*.js=X-Test: two",
+            "Cross-Origin-Embedder-Policy: credentialless",
+        ]
+        .iter()
+        .map(|spec| spec.parse().unwrap())
+        .collect(),
+        ..run::Options::default()
+    };
+    let server = serve_app(&dir, options);
+
+    let index = get(server.addr(), "/", "");
+    assert_eq!(index.headers("X-Test"), ["one"]);
+    // Rules given as options override the cross-origin isolation headers
+    assert_eq!(
+        index.headers("Cross-Origin-Embedder-Policy"),
+        ["credentialless"]
+    );
+    assert_eq!(index.headers("Cross-Origin-Opener-Policy"), ["same-origin"]);
+    let script = get(server.addr(), "/app.js", "");
+    assert_eq!(script.headers("X-Test"), ["two"]);
+}
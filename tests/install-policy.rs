@@ -0,0 +1,13 @@
+extern crate wasm_bin;
+
+use wasm_bin::build::InstallPolicy;
+
+#[test]
+fn parse_install_policy() {
+    assert_eq!("always".parse(), Ok(InstallPolicy::Always));
+    assert_eq!("yes".parse(), Ok(InstallPolicy::Always));
+    assert_eq!("never".parse(), Ok(InstallPolicy::Never));
+    assert_eq!("prompt".parse(), Ok(InstallPolicy::Prompt));
+    assert!("sometimes".parse::<InstallPolicy>().is_err());
+    assert_eq!(InstallPolicy::default(), InstallPolicy::Prompt);
+}
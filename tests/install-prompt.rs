@@ -0,0 +1,89 @@
+#![cfg(unix)]
+
+extern crate libc;
+extern crate wasm_bin;
+
+mod utils;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::os::unix::io::FromRawFd;
+use std::process::{Output, Stdio};
+use std::ptr;
+
+use utils::{fake_project, wait, wasm_bin_build};
+
+fn assert_not_installed(output: &Output) {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("NotInstalledError"),
+        "{}\n{}",
+        stdout,
+        stderr
+    );
+    assert!(
+        stdout.contains("wasm-bindgen 0.2.87 is not installed"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn decline_install_without_stdin() {
+    let dir = fake_project("install-policy-no-stdin");
+    let output = wait(wasm_bin_build(&dir).stdin(Stdio::null()).spawn().unwrap());
+    assert_not_installed(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("stdin is not a terminal"));
+}
+
+#[test]
+fn decline_install_at_end_of_input() {
+    let dir = fake_project("install-policy-eof");
+    // A terminal that is closed without answering the prompt
+    let (mut master, mut slave) = (0, 0);
+    let opened = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null(),
+            ptr::null(),
+        )
+    };
+    assert_eq!(opened, 0);
+    let master = unsafe { File::from_raw_fd(master) };
+    let slave = unsafe { Stdio::from_raw_fd(slave) };
+    let child = wasm_bin_build(&dir).stdin(slave).spawn().unwrap();
+    // End of file, as typed with Ctrl-D
+    (&master).write_all(b"\x04").unwrap();
+    let output = wait(child);
+    assert_not_installed(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Do you want to install it"), "{}", stdout);
+    assert!(
+        stdout.contains("No answer before the end of input"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn reject_invalid_policy() {
+    let dir = fake_project("install-policy-invalid");
+    let output = wait(
+        wasm_bin_build(&dir)
+            .env("WASM_BIN_INSTALL", "sometimes")
+            .stdin(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(
+        "wasm-bin: Invalid WASM_BIN_INSTALL: unknown install policy `sometimes`, expected always, \
+         never or prompt"
+    ));
+    assert!(!stdout.contains("Starting cargo build step"));
+}
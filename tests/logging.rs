@@ -0,0 +1,140 @@
+extern crate wasm_bin;
+
+mod utils;
+
+use std::fs;
+use std::io::prelude::*;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::thread;
+
+use wasm_bin::run;
+use wasm_bin::run::LogFormat;
+
+use utils::{get, serve_app};
+
+// Serves a request logged in a format and returns the log line
+fn log_request(format: LogFormat, name: &str, options: run::Options, path: &str) -> String {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target/tests")
+        .join(name);
+    let log_file = dir.join("requests.log");
+    let _ = fs::remove_file(&log_file);
+    let options = run::Options {
+        log_format: format,
+        log_file: Some(log_file.clone()),
+        ..options
+    };
+    let server = serve_app(&dir, options);
+    get(
+        server.addr(),
+        path,
+        "Referer: http://localhost/\r\nUser-Agent: test-agent/1.0\r\n",
+    );
+    server.shutdown().unwrap();
+
+    let log = fs::read_to_string(&log_file).unwrap();
+    assert_eq!(log.lines().count(), 1, "{}", log);
+    log.trim_end().to_string()
+}
+
+// A logged time with its digits as `0` and its month as `Mon`
+fn time_format(time: &str) -> String {
+    let mut format: String = time
+        .chars()
+        .map(|c| if c.is_ascii_digit() { '0' } else { c })
+        .collect();
+    for month in &[
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ] {
+        format = format.replace(month, "Mon");
+    }
+    format
+}
+
+#[test]
+fn log_common_format() {
+    let line = log_request(
+        LogFormat::Common,
+        "logging-common",
+        run::Options::default(),
+        "/app.js?v=1",
+    );
+    let (prefix, rest) = line.split_at(line.find('[').unwrap());
+    assert_eq!(prefix, "127.0.0.1 - - ");
+    let end = rest.find(']').unwrap();
+    assert_eq!(time_format(&rest[1..end]), "00/Mon/0000:00:00:00 +0000");
+    assert_eq!(&rest[end + 1..], " \"GET /app.js?v=1 HTTP/1.1\" 200 6");
+}
+
+#[test]
+fn log_combined_format() {
+    let line = log_request(
+        LogFormat::Combined,
+        "logging-combined",
+        run::Options::default(),
+        "/app.js?v=1",
+    );
+    let (prefix, rest) = line.split_at(line.find('[').unwrap());
+    assert_eq!(prefix, "127.0.0.1 - - ");
+    let end = rest.find(']').unwrap();
+    assert_eq!(time_format(&rest[1..end]), "00/Mon/0000:00:00:00 +0000");
+    assert_eq!(
+        &rest[end + 1..],
+        " \"GET /app.js?v=1 HTTP/1.1\" 200 6 \"http://localhost/\" \"test-agent/1.0\""
+    );
+}
+
+#[test]
+fn log_json_lines() {
+    let line = log_request(
+        LogFormat::Json,
+        "logging-json",
+        run::Options::default(),
+        "/app.js?v=1",
+    );
+    assert!(line.contains("\"method\":\"GET\""), "{}", line);
+    assert!(line.contains("\"uri\":\"/app.js?v=1\""), "{}", line);
+    assert!(line.contains("\"status\":200"), "{}", line);
+    assert!(line.contains("\"bytes\":6"), "{}", line);
+
+    // An ISO 8601 time, e.g. `1994-11-06T08:49:37Z`
+    let start = line.find("\"time\":\"").unwrap() + 8;
+    let end = start + line[start..].find('"').unwrap();
+    assert_eq!(time_format(&line[start..end]), "0000-00-00T00:00:00Z");
+}
+
+// Answers with a body of `length` bytes that ends with the connection, without a
+// Content-Length
+fn streaming_upstream(length: usize) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let mut stream = listener.accept().unwrap().0;
+        let mut head = Vec::new();
+        let mut byte = [0; 1];
+        while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+            head.push(byte[0]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        stream.write_all(&vec![b'x'; length]).unwrap();
+    });
+    port
+}
+
+#[test]
+fn log_bytes_of_streamed_responses() {
+    let rule = format!("/api/*=http://127.0.0.1:{}", streaming_upstream(100_000));
+    let options = run::Options {
+        proxy_rules: vec![rule.parse().unwrap()],
+        ..run::Options::default()
+    };
+    let line = log_request(LogFormat::Common, "logging-streamed", options, "/api/data");
+    assert!(
+        line.ends_with("\"GET /api/data HTTP/1.1\" 200 100000"),
+        "{}",
+        line
+    );
+}
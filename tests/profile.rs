@@ -0,0 +1,22 @@
+extern crate wasm_bin;
+
+use std::path::PathBuf;
+
+use wasm_bin::build;
+
+#[test]
+fn output_dir_per_profile() {
+    let mut options = build::Options::default();
+    assert_eq!(build::profile_dir(&options), "debug");
+    options.release = true;
+    assert_eq!(build::profile_dir(&options), "release");
+    options.release = false;
+    options.profile = Some("dev".to_string());
+    assert_eq!(build::profile_dir(&options), "debug");
+    options.profile = Some("profiling".to_string());
+    assert_eq!(build::profile_dir(&options), "profiling");
+    assert_eq!(
+        build::wasm_path("profiling", "app"),
+        PathBuf::from("./target/wasm-bin/profiling/app/app_bg.wasm")
+    );
+}
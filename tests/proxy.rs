@@ -0,0 +1,187 @@
+extern crate wasm_bin;
+
+mod utils;
+
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use wasm_bin::run;
+use wasm_bin::run::ProxyRule;
+
+use utils::{get, serve_app};
+
+#[test]
+fn parse_proxy_rules() {
+    let rule: ProxyRule = "/api/* = http://127.0.0.1:3000/v1".parse().unwrap();
+    assert!(rule.pattern.matches("/api/users"));
+    assert!(!rule.pattern.matches("/app.js"));
+    assert_eq!(rule.upstream.path(), "/v1");
+    assert_eq!(rule.upstream.port(), Some(3000));
+
+    assert!("/api/*".parse::<ProxyRule>().is_err());
+    assert!("/api/*=https://127.0.0.1:3000"
+        .parse::<ProxyRule>()
+        .is_err());
+    assert!("/api/*=/v1".parse::<ProxyRule>().is_err());
+    assert!("/api/[=http://127.0.0.1:3000".parse::<ProxyRule>().is_err());
+}
+
+// Reads from a stream until the end of a request or response head
+fn read_head(stream: &mut TcpStream) -> String {
+    let mut head = Vec::new();
+    let mut byte = [0; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte).unwrap() == 0 {
+            break;
+        }
+        head.push(byte[0]);
+    }
+    String::from_utf8(head).unwrap()
+}
+
+// Answers upgrade requests with the request line they arrived with and a greeting, then
+// echoes. Other requests get the request head the upstream received as their body, and a
+// header it lists in `Connection`.
+fn upstream() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                let head = read_head(&mut stream);
+                if !head.contains("Upgrade: websocket") {
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close, X-Upstream-Private\r\n\
+                         X-Upstream-Private: 1\r\nX-Upstream-Public: 1\r\n\r\n{}",
+                        head.len(),
+                        head
+                    )
+                    .unwrap();
+                    return;
+                }
+                let request_line = head.lines().next().unwrap().to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                     Connection: Upgrade\r\nX-Request-Line: {}\r\n\r\nhello",
+                    request_line
+                )
+                .unwrap();
+                let mut buf = [0; 1024];
+                loop {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => stream.write_all(&buf[..read]).unwrap(),
+                    }
+                }
+            });
+        }
+    });
+    port
+}
+
+#[test]
+fn forward_requests() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/proxy-forward");
+    let rule = format!("/api/*=http://127.0.0.1:{}/base/", upstream());
+    let options = run::Options {
+        proxy_rules: vec![rule.parse().unwrap()],
+        ..run::Options::default()
+    };
+    let server = serve_app(&dir, options);
+
+    let response = get(
+        server.addr(),
+        "/api/users?id=1",
+        "Connection: X-Client-Private\r\nX-Client-Private: 1\r\nX-Client-Public: 1\r\n",
+    );
+    assert_eq!(response.status(), 200);
+    let forwarded = response.text().to_lowercase();
+    assert!(forwarded.starts_with("get /base/api/users?id=1 http/1.1\r\n"));
+    assert!(forwarded.contains("x-forwarded-host: localhost\r\n"));
+    // Headers listed in `Connection` only apply to one connection, in both directions
+    assert!(
+        forwarded.contains("x-client-public: 1\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(!forwarded.contains("x-client-private"), "{}", forwarded);
+    assert_eq!(response.header("X-Upstream-Public"), Some("1"));
+    assert_eq!(response.header("X-Upstream-Private"), None);
+
+    // Unreachable upstreams are reported in the response
+    let options = run::Options {
+        proxy_rules: vec!["/api/*=http://127.0.0.1:1".parse().unwrap()],
+        ..run::Options::default()
+    };
+    let server = serve_app(&dir, options);
+    let response = get(server.addr(), "/api/users", "");
+    assert_eq!(response.status(), 502);
+    let message = response.text();
+    assert!(
+        message.starts_with("http://127.0.0.1:1/api/users: "),
+        "{}",
+        message
+    );
+}
+
+// Reads a response with a Content-Length from a connection that is kept open
+fn read_response(stream: &mut TcpStream) -> (String, Vec<u8>) {
+    let head = read_head(stream);
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).unwrap();
+    (head, body)
+}
+
+#[test]
+fn tunnel_websocket_upgrades() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/proxy");
+    let rule = format!("/ws/*=http://127.0.0.1:{}/base/", upstream());
+    let options = run::Options {
+        proxy_rules: vec![rule.parse().unwrap()],
+        ..run::Options::default()
+    };
+    let server = serve_app(&dir, options);
+
+    // Upgrades are recognized on connections kept alive after other requests too
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    for _ in 0..2 {
+        write!(stream, "GET /app.js HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let (head, body) = read_response(&mut stream);
+        assert!(head.starts_with("HTTP/1.1 200"));
+        assert_eq!(body, b"// app");
+    }
+    write!(
+        stream,
+        "GET /ws/chat?room=1 HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+         Connection: Upgrade\r\n\r\n"
+    )
+    .unwrap();
+    let head = read_head(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+    assert!(head.contains("Upgrade: websocket\r\n"), "{}", head);
+    // The target is prefixed with the path of the upstream URL
+    assert!(head.contains("X-Request-Line: GET /base/ws/chat?room=1 HTTP/1.1\r\n"));
+    // Including what the upstream sent along with its response
+    let mut greeting = [0; 5];
+    stream.read_exact(&mut greeting).unwrap();
+    assert_eq!(&greeting, b"hello");
+    stream.write_all(b"ping").unwrap();
+    let mut echo = [0; 4];
+    stream.read_exact(&mut echo).unwrap();
+    assert_eq!(&echo, b"ping");
+}
@@ -0,0 +1,89 @@
+extern crate wasm_bin;
+
+use std::fs;
+use std::path::PathBuf;
+
+use wasm_bin::{ScaffoldError, ScaffoldOptions, Template};
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target/tests")
+        .join(name);
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn new_binary_project() {
+    let dir = test_dir("scaffold-bin");
+    let options = ScaffoldOptions {
+        name: Some("my-app".to_string()),
+        html: true,
+        static_dir: true,
+        ..ScaffoldOptions::default()
+    };
+    let files = wasm_bin::new_project(&dir, &options).unwrap();
+    assert_eq!(
+        files,
+        vec![
+            dir.join("Cargo.toml"),
+            dir.join("src/main.rs"),
+            dir.join(".gitignore"),
+            dir.join("html/my-app.html"),
+            dir.join("static/style.css"),
+        ]
+    );
+    let manifest = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"my-app\""));
+    assert!(manifest.contains("wasm-bindgen = \"0.2\""));
+    assert!(!manifest.contains("cdylib"));
+    let template = fs::read_to_string(dir.join("html/my-app.html")).unwrap();
+    assert!(template.contains("{{loader}}"));
+    assert!(template.contains("style.css"));
+
+    match wasm_bin::new_project(&dir, &options) {
+        Err(ScaffoldError::ExistingDirectoryError(_)) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+    match wasm_bin::init_project(&dir, &options) {
+        Err(ScaffoldError::ExistingManifestError(_)) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn init_library_project() {
+    let dir = test_dir("scaffold-lib");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(".gitignore"), "/dist\n").unwrap();
+    let options = ScaffoldOptions {
+        template: Template::Lib,
+        html: true,
+        ..ScaffoldOptions::default()
+    };
+    let files = wasm_bin::init_project(&dir, &options).unwrap();
+    assert_eq!(
+        files,
+        vec![
+            dir.join("Cargo.toml"),
+            dir.join("src/lib.rs"),
+            dir.join("html/scaffold_lib.html"),
+        ]
+    );
+    let manifest = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"scaffold-lib\""));
+    assert!(manifest.contains("crate-type = [\"cdylib\"]"));
+    let source = fs::read_to_string(dir.join("src/lib.rs")).unwrap();
+    assert!(source.contains("#[wasm_bindgen]\npub fn main()"));
+
+    let options = ScaffoldOptions {
+        name: Some("1app".to_string()),
+        ..ScaffoldOptions::default()
+    };
+    let invalid_dir = test_dir("scaffold-invalid");
+    match wasm_bin::new_project(&invalid_dir, &options) {
+        Err(ScaffoldError::InvalidNameError(name)) => assert_eq!(name, "1app"),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(!invalid_dir.exists());
+}
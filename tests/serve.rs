@@ -0,0 +1,289 @@
+extern crate wasm_bin;
+
+mod utils;
+
+use std::fs;
+use std::net::TcpStream;
+use std::path::PathBuf;
+
+use wasm_bin::run;
+
+use utils::{get, local, request, serve_app, target};
+
+#[test]
+fn serve_targets_until_shutdown() {
+    let out_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/serve");
+    let targets = vec![target(&out_dir, "first"), target(&out_dir, "second")];
+    let server = wasm_bin::serve(&targets, &local(run::Options::default())).unwrap();
+    let addr = server.addr();
+    assert_ne!(addr.port(), 0);
+
+    let index = get(addr, "/", "");
+    assert_eq!(index.status(), 200);
+    assert!(index.text().contains("./first.js"));
+    assert_eq!(get(addr, "/first.js", "").body, b"// first");
+    assert_eq!(get(addr, "/second", "").status(), 301);
+    assert!(get(addr, "/second/", "").text().contains("./second.js"));
+    assert_eq!(get(addr, "/second/second.js", "").body, b"// second");
+
+    server.shutdown().unwrap();
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn forward_console_messages() {
+    let out_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/serve-console");
+    // Forwarded by default, like with `wasm-bin run`
+    let server = serve_app(&out_dir, run::Options::default());
+
+    assert!(get(server.addr(), "/", "")
+        .text()
+        .contains("/__wasm-bin/console"));
+    let message = r#"{"target": "app", "level": "error", "message": "panicked at src/main.rs"}"#;
+    let response = request(server.addr(), "POST", "/__wasm-bin/console", "", message);
+    assert_eq!(response.status(), 204);
+    server.shutdown().unwrap();
+
+    let options = run::Options {
+        forward_console: false,
+        ..run::Options::default()
+    };
+    let server = serve_app(&out_dir, options);
+    assert!(!get(server.addr(), "/", "")
+        .text()
+        .contains("/__wasm-bin/console"));
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[test]
+fn reject_paths_escaping_the_target() {
+    let out_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/serve-escape");
+    let server = serve_app(&out_dir, run::Options::default());
+    let secret = out_dir.join("secret.txt");
+    fs::write(&secret, "secret").unwrap();
+
+    let absolute = format!("/{}", percent_encode(&secret.to_string_lossy()));
+    for path in &[
+        absolute.as_str(),
+        "/..%2Fsecret.txt",
+        "/app/..%2F..%2Fsecret.txt",
+        "/..%5Csecret.txt",
+        "/../secret.txt",
+        "/%2E%2E/secret.txt",
+    ] {
+        let response = get(server.addr(), path, "");
+        assert_eq!(response.status(), 400, "{}: {}", path, response.head);
+        assert!(!response.text().contains("secret"), "{}", path);
+    }
+    assert_eq!(get(server.addr(), "/app.js", "").body, b"// app");
+    assert_eq!(get(server.addr(), "/%61pp.js", "").body, b"// app");
+}
+
+#[test]
+fn serve_directories() {
+    let out_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/serve-dirs");
+    fs::create_dir_all(out_dir.join("app/docs")).unwrap();
+    fs::write(out_dir.join("app/docs/index.html"), "docs index").unwrap();
+    fs::create_dir_all(out_dir.join("app/assets")).unwrap();
+    fs::write(out_dir.join("app/assets/logo.svg"), "<svg/>").unwrap();
+
+    let server = serve_app(&out_dir, run::Options::default());
+    let redirect = get(server.addr(), "/docs?page=2", "");
+    assert_eq!(redirect.status(), 301);
+    assert_eq!(redirect.header("Location"), Some("/docs/?page=2"));
+    assert_eq!(get(server.addr(), "/docs/", "").body, b"docs index");
+    // Without an index.html, directories aren't listed unless enabled
+    assert_eq!(get(server.addr(), "/assets/", "").status(), 404);
+    server.shutdown().unwrap();
+
+    let options = run::Options {
+        list_dirs: true,
+        ..run::Options::default()
+    };
+    let server = serve_app(&out_dir, options);
+    let listing = get(server.addr(), "/assets/", "");
+    assert_eq!(listing.status(), 200);
+    assert!(listing.text().contains("href=\"logo.svg\""));
+    assert_eq!(get(server.addr(), "/docs/", "").body, b"docs index");
+}
+
+#[test]
+fn serve_ranges() {
+    let out_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/serve-ranges");
+    let server = serve_app(&out_dir, run::Options::default());
+    // Larger than a streamed chunk, so ranges are read from the file as they are sent
+    let data: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+    fs::write(out_dir.join("app/data.bin"), &data).unwrap();
+    let addr = server.addr();
+
+    let response = get(addr, "/data.bin", "");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body, data);
+    let etag = response.header("ETag").unwrap().to_string();
+
+    let response = get(addr, "/data.bin", "Range: bytes=100-199\r\n");
+    assert_eq!(response.status(), 206);
+    assert_eq!(
+        response.header("Content-Range"),
+        Some("bytes 100-199/200000")
+    );
+    assert_eq!(response.body, &data[100..200]);
+
+    // Overlapping and adjacent ranges are merged into one part each
+    let response = get(
+        addr,
+        "/data.bin",
+        "Range: bytes=150000-199999,0-9,5-14,15-19\r\n",
+    );
+    assert_eq!(response.status(), 206);
+    let content_type = response.header("Content-Type").unwrap();
+    let boundary = content_type
+        .trim_start_matches("multipart/byteranges; boundary=")
+        .to_string();
+    assert_ne!(boundary, content_type);
+    assert_eq!(
+        response.header("Content-Length"),
+        Some(response.body.len().to_string().as_str())
+    );
+    let mut expected = Vec::new();
+    for &(start, end) in &[(0, 19), (150_000, 199_999)] {
+        expected.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Range: bytes {}-{}/200000\r\n\r\n",
+                boundary, start, end
+            )
+            .as_bytes(),
+        );
+        expected.extend_from_slice(&data[start..=end]);
+        expected.extend_from_slice(b"\r\n");
+    }
+    expected.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    assert!(response.body == expected);
+
+    // Too many parts get the full content instead
+    let many: Vec<String> = (0..20).map(|i| format!("{}-{}", i * 10, i * 10)).collect();
+    let response = get(
+        addr,
+        "/data.bin",
+        &format!("Range: bytes={}\r\n", many.join(",")),
+    );
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body.len(), data.len());
+
+    let response = get(addr, "/data.bin", "Range: bytes=300000-\r\n");
+    assert_eq!(response.status(), 416);
+    assert_eq!(response.header("Content-Range"), Some("bytes */200000"));
+    assert!(response.body.is_empty());
+
+    // If-Range only applies the range while the content is unchanged
+    let response = get(
+        addr,
+        "/data.bin",
+        &format!("Range: bytes=0-9\r\nIf-Range: {}\r\n", etag),
+    );
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.body, &data[..10]);
+    let response = get(
+        addr,
+        "/data.bin",
+        "Range: bytes=0-9\r\nIf-Range: \"stale\"\r\n",
+    );
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body, data);
+}
+
+#[test]
+fn answer_conditional_requests() {
+    let out_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/serve-conditional");
+    let server = serve_app(&out_dir, run::Options::default());
+    let addr = server.addr();
+
+    let response = get(addr, "/app.js", "");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body, b"// app");
+    let etag = response.header("ETag").unwrap().to_string();
+    let modified = response.header("Last-Modified").unwrap().to_string();
+    assert_eq!(response.header("Cache-Control"), Some("no-cache"));
+
+    let status = |headers: String| {
+        let response = get(addr, "/app.js", &headers);
+        if response.status() == 304 {
+            assert!(response.body.is_empty());
+        }
+        response.status()
+    };
+    assert_eq!(status(format!("If-None-Match: {}\r\n", etag)), 304);
+    assert_eq!(
+        status(format!("If-None-Match: \"other\", {}\r\n", etag)),
+        304
+    );
+    assert_eq!(status("If-None-Match: *\r\n".to_string()), 304);
+    assert_eq!(status("If-None-Match: \"other\"\r\n".to_string()), 200);
+    assert_eq!(status(format!("If-Modified-Since: {}\r\n", modified)), 304);
+    assert_eq!(
+        status("If-Modified-Since: Mon, 01 Jan 2001 00:00:00 GMT\r\n".to_string()),
+        200
+    );
+    // If-None-Match takes precedence over If-Modified-Since
+    assert_eq!(
+        status(format!(
+            "If-None-Match: \"other\"\r\nIf-Modified-Since: {}\r\n",
+            modified
+        )),
+        200
+    );
+
+    // Changing the file changes its validators
+    fs::write(out_dir.join("app/app.js"), "// changed app").unwrap();
+    let response = get(addr, "/app.js", &format!("If-None-Match: {}\r\n", etag));
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body, b"// changed app");
+    assert_ne!(response.header("ETag"), Some(etag.as_str()));
+}
+
+#[test]
+fn serve_index_for_navigations() {
+    let out_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/serve-spa");
+    let targets = vec![target(&out_dir, "first"), target(&out_dir, "second")];
+    let options = run::Options {
+        spa: true,
+        ..run::Options::default()
+    };
+    let server = wasm_bin::serve(&targets, &local(options)).unwrap();
+    let addr = server.addr();
+    let navigate = "Accept: text/html,application/xhtml+xml,*/*;q=0.8\r\n";
+
+    // The index links its files from the base of its target, so they load at any depth
+    let response = get(addr, "/settings", navigate);
+    assert_eq!(response.status(), 200);
+    assert!(response.text().contains("'/first.js'"));
+    let response = get(addr, "/second/level/3", navigate);
+    assert_eq!(response.status(), 200);
+    let body = response.text();
+    assert!(body.contains("'/second/second.js'"), "{}", body);
+    assert!(body.contains("/second/second_bg.wasm"), "{}", body);
+    assert_eq!(get(addr, "/second/second.js", "").body, b"// second");
+
+    // Missing assets and requests that aren't navigations still get a 404
+    assert_eq!(get(addr, "/second/missing.js", navigate).status(), 404);
+    assert_eq!(
+        get(addr, "/second/second_bg.wasm", "Accept: */*\r\n").status(),
+        404
+    );
+    assert_eq!(
+        get(addr, "/second/level/3", "Accept: application/json\r\n").status(),
+        404
+    );
+    server.shutdown().unwrap();
+
+    let server = wasm_bin::serve(&targets, &local(run::Options::default())).unwrap();
+    assert_eq!(get(server.addr(), "/settings", navigate).status(), 404);
+}
@@ -0,0 +1,54 @@
+#![cfg(unix)]
+
+extern crate wasm_bin;
+
+mod utils;
+
+use std::fs;
+use std::process::Stdio;
+
+use utils::{fake_bindgen, fake_project, wait, wasm_bin_build};
+
+#[test]
+fn copy_static_files_without_replacing_outputs() {
+    let dir = fake_project("static-files");
+    let bindgen = fake_bindgen(&dir);
+    fs::create_dir_all(dir.join("static/images")).unwrap();
+    fs::write(dir.join("static/style.css"), "body {}").unwrap();
+    fs::write(dir.join("static/images/logo.svg"), "<svg/>").unwrap();
+    fs::write(dir.join("static/index.html"), "static index").unwrap();
+    fs::write(dir.join("static/app.js"), "// static").unwrap();
+
+    let output = wait(
+        wasm_bin_build(&dir)
+            .env("WASM_BINDGEN", &bindgen)
+            .stdin(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}\n{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let out_dir = dir.join("target/wasm-bin/debug/app");
+    assert_eq!(
+        fs::read_to_string(out_dir.join("style.css")).unwrap(),
+        "body {}"
+    );
+    assert!(out_dir.join("images/logo.svg").is_file());
+    assert!(fs::read_to_string(out_dir.join("index.html"))
+        .unwrap()
+        .contains("app.js"));
+    assert_eq!(
+        fs::read_to_string(out_dir.join("app.js")).unwrap(),
+        "// bindings\n"
+    );
+    for skipped in &["static/index.html", "static/app.js"] {
+        let warning = format!("not copying {}", dir.join(skipped).display());
+        assert!(stdout.contains(&warning), "{}", stdout);
+    }
+}
@@ -0,0 +1,73 @@
+extern crate wasm_bin;
+
+mod utils;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use utils::{named_module, request, serve_app};
+use wasm_bin::run;
+use wasm_bin::{Frame, Symbols};
+
+const MANGLED_MAIN: &str = "_ZN3app4main17h0123456789abcdefE";
+
+fn module() -> (Vec<u8>, u64) {
+    named_module(MANGLED_MAIN)
+}
+
+#[test]
+fn lookup_function_names() {
+    let (module, body_offset) = module();
+    let symbols = Symbols::parse(&module).unwrap();
+    let main = vec![Frame {
+        function: Some("app::main".to_string()),
+        file: None,
+        line: None,
+        column: None,
+    }];
+    assert_eq!(symbols.lookup(Some(1), None), main);
+    assert_eq!(symbols.lookup(None, Some(body_offset + 1)), main);
+    assert_eq!(symbols.lookup(Some(0), None), vec![]);
+}
+
+#[test]
+fn symbolicate_stack_trace() {
+    let (module, _) = module();
+    let symbols = Symbols::parse(&module).unwrap();
+    let trace = "RuntimeError: unreachable\n    at app_bg.wasm.wasm-function[1] (wasm://wasm/app:wasm-function[1]:0x2f)\n    at main.js:10:3";
+    assert_eq!(
+        symbols.symbolicate(trace),
+        "RuntimeError: unreachable\n    at app_bg.wasm.wasm-function[1] (wasm://wasm/app:wasm-function[1]:0x2f) => app::main\n    at main.js:10:3"
+    );
+}
+
+#[test]
+fn symbolicate_with_the_current_module() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/symbolicate-serve");
+    let server = serve_app(&dir, run::Options::default());
+    let wasm_path = dir.join("app/app_bg.wasm");
+    let symbolicate = || {
+        let trace = "    at app_bg.wasm.wasm-function[1] (wasm://wasm/app:wasm-function[1]:0x2f)";
+        let body = format!(r#"{{"target": "app", "trace": "{}"}}"#, trace);
+        request(server.addr(), "POST", "/__wasm-bin/symbolicate", "", &body).text()
+    };
+
+    fs::write(&wasm_path, module().0).unwrap();
+    assert!(symbolicate().ends_with(" => app::main"));
+    assert!(symbolicate().ends_with(" => app::main"));
+
+    // A rebuilt module replaces the symbols loaded before
+    fs::write(
+        &wasm_path,
+        named_module("_ZN3app5start17h0123456789abcdefE").0,
+    )
+    .unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&wasm_path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(10))
+        .unwrap();
+    assert!(symbolicate().ends_with(" => app::start"));
+}
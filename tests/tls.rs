@@ -0,0 +1,158 @@
+extern crate openssl;
+extern crate wasm_bin;
+
+mod utils;
+
+use std::fs;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use openssl::asn1::Asn1Time;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509NameBuilder, X509};
+
+use wasm_bin::run;
+
+use utils::serve_app;
+
+const CERT: &str = "target/wasm-bin/certs/localhost.pem";
+
+fn fingerprint(pem: &[u8]) -> String {
+    let digest = X509::from_pem(pem)
+        .unwrap()
+        .digest(MessageDigest::sha256())
+        .unwrap();
+    let hex: Vec<String> = digest.iter().map(|b| format!("{:02X}", b)).collect();
+    hex.join(":")
+}
+
+// A self-signed certificate for `dns_name`, valid for a month or expired years ago
+fn certificate(dns_name: &str, expired: bool) -> (Vec<u8>, Vec<u8>) {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", dns_name).unwrap();
+    let name = name.build();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    let (not_before, not_after) = if expired {
+        (
+            Asn1Time::from_unix(1_600_000_000).unwrap(),
+            Asn1Time::from_unix(1_600_086_400).unwrap(),
+        )
+    } else {
+        (
+            Asn1Time::days_from_now(0).unwrap(),
+            Asn1Time::days_from_now(30).unwrap(),
+        )
+    };
+    builder.set_not_before(&not_before).unwrap();
+    builder.set_not_after(&not_after).unwrap();
+    let subject_alt_names = SubjectAlternativeName::new()
+        .dns(dns_name)
+        .build(&builder.x509v3_context(None, None))
+        .unwrap();
+    builder.append_extension(subject_alt_names).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    (
+        builder.build().to_pem().unwrap(),
+        key.private_key_to_pem_pkcs8().unwrap(),
+    )
+}
+
+// Serves over https with the certificate cached in `dir/certs` and returns the certificate it
+// presented
+fn served_certificate(dir: &Path) -> Vec<u8> {
+    let options = run::Options {
+        https: true,
+        cert_dir: Some(dir.join("certs")),
+        ..run::Options::default()
+    };
+    let server = serve_app(dir, options);
+    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+    connector.set_verify(SslVerifyMode::NONE);
+    let stream = connector
+        .build()
+        .connect("localhost", TcpStream::connect(server.addr()).unwrap())
+        .unwrap();
+    let cert = stream.ssl().peer_certificate().unwrap().to_pem().unwrap();
+    server.shutdown().unwrap();
+    cert
+}
+
+#[test]
+fn cache_self_signed_certificate() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/tests/tls");
+    let _ = fs::remove_dir_all(&dir);
+    let (cert_path, key_path) = (
+        dir.join("certs/localhost.pem"),
+        dir.join("certs/localhost.key"),
+    );
+
+    let served = served_certificate(&dir);
+    let cert = fs::read(&cert_path).unwrap();
+    assert_eq!(fingerprint(&served), fingerprint(&cert));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // The cached certificate is reused while it is valid for the expected names
+    assert_eq!(fingerprint(&served_certificate(&dir)), fingerprint(&cert));
+
+    // Certificates for other names, or expired ones, are replaced
+    for &(dns_name, expired) in &[("example.com", false), ("localhost", true)] {
+        let (stale, key) = certificate(dns_name, expired);
+        fs::write(&cert_path, &stale).unwrap();
+        fs::write(&key_path, &key).unwrap();
+        let served = served_certificate(&dir);
+        assert_ne!(fingerprint(&served), fingerprint(&stale));
+        assert_eq!(
+            fingerprint(&served),
+            fingerprint(&fs::read(&cert_path).unwrap())
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn print_certificate_fingerprint() {
+    use std::io::prelude::*;
+    use std::io::BufReader;
+    use std::process::Stdio;
+    use utils::{fake_bindgen, fake_project, wasm_bin};
+
+    let dir = fake_project("tls-fingerprint");
+    let bindgen = fake_bindgen(&dir);
+    let mut child = wasm_bin(&dir, "run")
+        .arg("--https")
+        .env("WASM_BINDGEN", &bindgen)
+        .stdin(Stdio::null())
+        .spawn()
+        .unwrap();
+    let line = BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .map(Result::unwrap)
+        .find(|line| line.contains("TLS certificate"));
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let line = line.expect("no certificate message");
+    let cert = fs::read(dir.join(CERT)).unwrap();
+    assert!(
+        line.ends_with(&format!("(SHA-256 fingerprint {})", fingerprint(&cert))),
+        "{}",
+        line
+    );
+}
@@ -1,3 +1,6 @@
+// Each test crate only uses some of these helpers
+#![allow(dead_code)]
+
 extern crate wasm_bin;
 
 use std;
@@ -6,10 +9,23 @@ use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-use std::path::Path;
+use std::net::{SocketAddr, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use wasm_bin::build;
 use wasm_bin::build::Options as BuildOptions;
+use wasm_bin::build::{PackageType, TargetPackage};
+use wasm_bin::run;
+
+#[path = "../../src/test_wasm.rs"]
+mod test_wasm;
+#[allow(unused_imports)]
+pub use self::test_wasm::{custom_section, main_module, named_module, section};
 
 fn path_exists(path: &Path) -> bool {
     match fs::metadata(path) {
@@ -76,3 +92,175 @@ impl TestProject {
         Ok(())
     }
 }
+
+#[cfg(unix)]
+fn write_script(path: &Path, script: &str) {
+    fs::write(path, script).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// A project in target/tests whose build is faked by a `cargo` script in its `bin` directory,
+/// which reports a prebuilt module as the artifact of a binary `app`. Its Cargo.lock locks
+/// wasm-bindgen 0.2.87.
+#[cfg(unix)]
+pub fn fake_project(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target/tests")
+        .join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("bin")).unwrap();
+    fs::write(dir.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+    fs::write(
+        dir.join("Cargo.lock"),
+        "[[package]]\nname = \"wasm-bindgen\"\nversion = \"0.2.87\"\n",
+    )
+    .unwrap();
+    let wasm = dir.join("app.wasm");
+    fs::write(&wasm, main_module()).unwrap();
+    let artifact = format!(
+        r#"{{"reason":"compiler-artifact","package_id":"app 0.1.0 (path+file://{dir})","target":{{"kind":["bin"],"crate_types":["bin"],"name":"app","src_path":"{dir}/src/main.rs"}},"profile":{{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false}},"features":[],"filenames":["{wasm}"],"fresh":true}}"#,
+        dir = dir.display(),
+        wasm = wasm.display()
+    );
+    write_script(
+        &dir.join("bin/cargo"),
+        &format!(
+            "#!/bin/sh\ncase \"$1\" in\n  build) echo '{}' ;;\n  locate-project) echo '{}' ;;\n  *) exit 1 ;;\nesac\n",
+            artifact,
+            dir.join("Cargo.toml").display()
+        ),
+    );
+    dir
+}
+
+/// A wasm-bindgen script for a fake project, which copies the module to the output directory
+/// and writes placeholder bindings
+#[cfg(unix)]
+pub fn fake_bindgen(dir: &Path) -> PathBuf {
+    let path = dir.join("bin/wasm-bindgen");
+    write_script(
+        &path,
+        "#!/bin/sh\n/bin/cp \"$1\" \"$5/app_bg.wasm\" && echo '// bindings' > \"$5/app.js\"\n",
+    );
+    path
+}
+
+/// `wasm-bin build` in a fake project, with only its scripts on the PATH and an empty cache
+pub fn wasm_bin_build(dir: &Path) -> Command {
+    wasm_bin(dir, "build")
+}
+
+/// A wasm-bin subcommand in a fake project, set up like `wasm_bin_build`
+pub fn wasm_bin(dir: &Path, subcommand: &str) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_wasm-bin"));
+    cmd.arg(subcommand)
+        .current_dir(dir)
+        .env("PATH", dir.join("bin"))
+        .env("WASM_BIN_CACHE_DIR", dir.join("cache"))
+        .env_remove("WASM_BINDGEN")
+        .env_remove("WASM_BIN_INSTALL")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    cmd
+}
+
+/// Waits for a process, failing the test instead of hanging if it never exits
+pub fn wait(mut child: Child) -> Output {
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > Duration::from_secs(30) {
+            child.kill().unwrap();
+            panic!(
+                "wasm-bin didn't exit: {:?}",
+                child.wait_with_output().unwrap()
+            );
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    child.wait_with_output().unwrap()
+}
+
+/// A binary target in a directory of `out_dir`, whose script `<name>.js` contains `// <name>`
+pub fn target(out_dir: &Path, name: &str) -> TargetPackage {
+    let dir = out_dir.join(name);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.js", name));
+    fs::write(&path, format!("// {}", name)).unwrap();
+    TargetPackage {
+        ty: PackageType::Binary,
+        name: name.to_string(),
+        wasm_path: dir.join(format!("{}_bg.wasm", name)),
+        path,
+    }
+}
+
+/// Options serving on a free port of the loopback interface
+pub fn local(options: run::Options) -> run::Options {
+    run::Options {
+        addr: Some("127.0.0.1:0".parse().unwrap()),
+        ..options
+    }
+}
+
+/// Serves an `app` target in `out_dir` on a free port
+pub fn serve_app(out_dir: &Path, options: run::Options) -> run::Server {
+    wasm_bin::serve(&[target(out_dir, "app")], &local(options)).unwrap()
+}
+
+/// Head and body of a response
+pub struct Response {
+    pub head: String,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn status(&self) -> u16 {
+        self.head[9..12].parse().unwrap()
+    }
+
+    /// Values of a header, in order
+    pub fn headers(&self, name: &str) -> Vec<&str> {
+        let prefix = format!("{}: ", name.to_lowercase());
+        self.head
+            .lines()
+            .filter(|line| line.to_lowercase().starts_with(&prefix))
+            .map(|line| &line[prefix.len()..])
+            .collect()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers(name).first().cloned()
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Sends a request with extra header lines on a connection of its own
+pub fn request(addr: SocketAddr, method: &str, path: &str, headers: &str, body: &str) -> Response {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n{}\r\n{}",
+        method,
+        path,
+        body.len(),
+        headers,
+        body
+    )
+    .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let body = response.split_off(end);
+    Response {
+        head: String::from_utf8(response).unwrap(),
+        body,
+    }
+}
+
+/// Sends a GET request with extra header lines, e.g. `"Range: bytes=0-9\r\n"`
+pub fn get(addr: SocketAddr, path: &str, headers: &str) -> Response {
+    request(addr, "GET", path, headers, "")
+}